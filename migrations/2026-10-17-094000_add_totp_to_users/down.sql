@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE recovery_codes;
+ALTER TABLE users DROP COLUMN totp_enabled_at;
+ALTER TABLE users DROP COLUMN totp_secret;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX users_email_key;
+DROP INDEX users_username_key;
+ALTER TABLE users ADD CONSTRAINT users_username_email_key UNIQUE (username, email);
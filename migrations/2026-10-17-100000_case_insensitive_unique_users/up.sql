@@ -0,0 +1,23 @@
+-- Accounts that only differ in case can't be told apart anymore, so they have to be sorted out
+-- by hand before the new indexes can be created
+DO $$
+DECLARE
+    conflicts TEXT;
+BEGIN
+    SELECT string_agg(names, '; ') INTO conflicts FROM (
+        SELECT 'username ' || string_agg(username, ', ' ORDER BY created_at) AS names
+        FROM users GROUP BY lower(username) HAVING count(*) > 1
+        UNION ALL
+        SELECT 'email ' || string_agg(email, ', ' ORDER BY created_at)
+        FROM users GROUP BY lower(email) HAVING count(*) > 1
+    ) AS duplicates;
+
+    IF conflicts IS NOT NULL THEN
+        RAISE EXCEPTION 'users share a username or email that only differs in case: %', conflicts
+            USING HINT = 'Rename or remove the duplicate accounts, then run the migration again';
+    END IF;
+END $$;
+
+ALTER TABLE users DROP CONSTRAINT users_username_email_key;
+CREATE UNIQUE INDEX users_username_key ON users (lower(username));
+CREATE UNIQUE INDEX users_email_key ON users (lower(email));
@@ -0,0 +1,9 @@
+ALTER TABLE articles
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'published' CHECK (status IN ('draft', 'published', 'unlisted')),
+    ADD COLUMN published_at TIMESTAMP;
+
+-- everything written so far went public right away
+UPDATE articles SET published_at = created_at;
+
+CREATE INDEX articles_published_at_idx ON articles (published_at DESC) WHERE status = 'published';
+CREATE INDEX articles_author_id_status_idx ON articles (author_id, status);
@@ -0,0 +1,4 @@
+ALTER TABLE articles ADD COLUMN publish_at TIMESTAMP;
+
+-- the scheduler only ever looks for articles that are due
+CREATE INDEX articles_publish_at_idx ON articles (publish_at) WHERE publish_at IS NOT NULL;
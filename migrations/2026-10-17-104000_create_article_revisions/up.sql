@@ -0,0 +1,10 @@
+-- the versions an article had before each edit, numbered from 1 per article
+CREATE TABLE article_revisions (
+    article_id UUID NOT NULL REFERENCES articles (id),
+    number INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (article_id, number)
+);
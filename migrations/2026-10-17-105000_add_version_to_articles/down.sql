@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER bump_article_version ON articles;
+DROP FUNCTION bump_article_version();
+ALTER TABLE articles DROP COLUMN version;
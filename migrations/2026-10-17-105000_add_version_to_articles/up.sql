@@ -0,0 +1,15 @@
+ALTER TABLE articles ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+-- bumped on every change that touches the row, however it was made, so the ETag built from it
+-- changes with the article
+CREATE OR REPLACE FUNCTION bump_article_version() RETURNS trigger AS $$
+BEGIN
+    IF (NEW IS DISTINCT FROM OLD AND NEW.version IS NOT DISTINCT FROM OLD.version) THEN
+        NEW.version := OLD.version + 1;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER bump_article_version BEFORE UPDATE ON articles
+    FOR EACH ROW EXECUTE PROCEDURE bump_article_version();
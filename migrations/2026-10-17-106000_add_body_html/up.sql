@@ -0,0 +1,4 @@
+-- the body rendered from Markdown and sanitized, written along with the body; rows from before
+-- are left NULL and rendered when they're read
+ALTER TABLE articles ADD COLUMN body_html TEXT;
+ALTER TABLE comments ADD COLUMN body_html TEXT;
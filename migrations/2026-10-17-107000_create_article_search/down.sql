@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER refresh_article_search ON article_tags;
+DROP TRIGGER refresh_article_search ON articles;
+DROP FUNCTION refresh_article_search_on_tag();
+DROP FUNCTION refresh_article_search_on_article();
+DROP FUNCTION refresh_article_search(UUID);
+DROP TABLE article_search;
@@ -0,0 +1,50 @@
+-- the search document of every article, kept in a table of its own so refreshing it doesn't count
+-- as an edit of the article (see bump_article_version). It's derived from the article and its tags
+-- and has nothing worth keeping, so it goes when the article goes.
+CREATE TABLE article_search (
+    article_id UUID PRIMARY KEY REFERENCES articles (id) ON DELETE CASCADE,
+    document TSVECTOR NOT NULL
+);
+
+CREATE INDEX article_search_document_idx ON article_search USING GIN (document);
+
+-- title and tags weigh the most, then the description, then the body
+CREATE OR REPLACE FUNCTION refresh_article_search(refreshed_id UUID) RETURNS void AS $$
+    INSERT INTO article_search (article_id, document)
+    SELECT articles.id,
+        setweight(to_tsvector('english', articles.title), 'A') ||
+        setweight(to_tsvector('english', coalesce(string_agg(article_tags.tag_name, ' '), '')), 'A') ||
+        setweight(to_tsvector('english', articles.description), 'B') ||
+        setweight(to_tsvector('english', articles.body), 'C')
+    FROM articles
+    LEFT JOIN article_tags ON article_tags.article_id = articles.id
+    WHERE articles.id = refreshed_id
+    GROUP BY articles.id
+    ON CONFLICT (article_id) DO UPDATE SET document = EXCLUDED.document;
+$$ LANGUAGE sql;
+
+CREATE OR REPLACE FUNCTION refresh_article_search_on_article() RETURNS trigger AS $$
+BEGIN
+    PERFORM refresh_article_search(NEW.id);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION refresh_article_search_on_tag() RETURNS trigger AS $$
+BEGIN
+    IF (TG_OP = 'DELETE') THEN
+        PERFORM refresh_article_search(OLD.article_id);
+    ELSE
+        PERFORM refresh_article_search(NEW.article_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER refresh_article_search AFTER INSERT OR UPDATE OF title, description, body
+    ON articles FOR EACH ROW EXECUTE PROCEDURE refresh_article_search_on_article();
+
+CREATE TRIGGER refresh_article_search AFTER INSERT OR DELETE
+    ON article_tags FOR EACH ROW EXECUTE PROCEDURE refresh_article_search_on_tag();
+
+SELECT refresh_article_search(id) FROM articles;
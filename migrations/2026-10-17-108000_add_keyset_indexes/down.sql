@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX comments_article_id_created_at_id_idx;
+DROP INDEX articles_published_at_id_idx;
+CREATE INDEX articles_published_at_idx ON articles (published_at DESC) WHERE status = 'published';
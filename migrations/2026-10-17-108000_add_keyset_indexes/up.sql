@@ -0,0 +1,5 @@
+-- lists are paged with cursors on their sort order, these indexes have it with the id as tiebreaker
+-- so a page deep in a list is found without reading the pages before it
+DROP INDEX articles_published_at_idx;
+CREATE INDEX articles_published_at_id_idx ON articles (published_at DESC, id DESC) WHERE status = 'published';
+CREATE INDEX comments_article_id_created_at_id_idx ON comments (article_id, created_at, id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users DROP COLUMN totp_last_used_step;
+DROP TABLE login_challenges;
@@ -0,0 +1,18 @@
+-- second steps of logins that are waiting for a code, so each challenge token works once and only
+-- for a few wrong codes
+CREATE TABLE login_challenges (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users (id),
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX login_challenges_user_id_idx ON login_challenges (user_id);
+
+SELECT diesel_manage_updated_at('login_challenges');
+
+-- the time step of the last authenticator code that was accepted, it and earlier ones are refused
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT;
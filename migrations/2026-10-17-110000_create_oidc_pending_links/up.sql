@@ -0,0 +1,17 @@
+-- identities whose email matches an existing account, waiting for the account's owner to confirm
+-- that they belong together
+CREATE TABLE oidc_pending_links (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users (id),
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    email TEXT,
+    token_hash TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX oidc_pending_links_user_id_idx ON oidc_pending_links (user_id);
+
+SELECT diesel_manage_updated_at('oidc_pending_links');
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE oidc_login_states DROP COLUMN binding_hash;
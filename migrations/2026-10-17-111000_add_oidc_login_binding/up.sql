@@ -0,0 +1,5 @@
+-- ties each sign-in to the browser that started it, through a cookie only that browser has.
+-- Sign-ins in progress can't be finished without it, they have to be started again.
+DELETE FROM oidc_login_states;
+
+ALTER TABLE oidc_login_states ADD COLUMN binding_hash TEXT NOT NULL;
@@ -0,0 +1,286 @@
+use actix_web::{HttpRequest, HttpResponse, web::Json, web::Path, web::Query, web::Data};
+use serde_json::Value as JsonValue;
+use actix_http::error::ResponseError;
+use futures::Future;
+use uuid::Uuid;
+
+use super::AppState;
+use crate::prelude::*;
+use crate::utils::{
+    auth::{authenticate, Auth},
+    permissions::Role,
+    CustomDateTime,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct In<U> {
+    user: U,
+}
+
+// Extractors ↓
+
+#[derive(Debug, Deserialize)]
+pub struct UserPath {
+    id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsersParams {
+    // matched against usernames and emails
+    pub q: Option<String>,
+    pub role: Option<String>,
+    pub suspended: Option<bool>,
+    pub limit: Option<usize>,  // <- if not set, is 20
+    pub offset: Option<usize>, // <- if not set, is 0
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditParams {
+    // the id of the user who acted
+    pub actor: Option<Uuid>,
+    pub action: Option<String>,
+    // RFC 3339, e.g. 2019-06-01T12:00:00Z
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<usize>,  // <- if not set, is 20
+    pub offset: Option<usize>, // <- if not set, is 0
+}
+
+// Client Messages ↓
+
+#[derive(Debug)]
+pub struct GetUsers {
+    pub auth: Auth,
+    pub params: UsersParams,
+}
+
+#[derive(Debug)]
+pub struct SuspendUser {
+    pub auth: Auth,
+    pub id: Uuid,
+}
+
+#[derive(Debug)]
+pub struct UnsuspendUser {
+    pub auth: Auth,
+    pub id: Uuid,
+}
+
+#[derive(Debug)]
+pub struct ForcePasswordReset {
+    pub auth: Auth,
+    pub id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeRole {
+    pub role: String,
+}
+
+#[derive(Debug)]
+pub struct ChangeRoleOuter {
+    pub auth: Auth,
+    pub id: Uuid,
+    pub change_role: ChangeRole,
+}
+
+#[derive(Debug)]
+pub struct DeleteUser {
+    pub auth: Auth,
+    pub id: Uuid,
+}
+
+#[derive(Debug)]
+pub struct GetAuditEvents {
+    pub auth: Auth,
+    pub params: AuditParams,
+}
+
+// JSON response objects ↓
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserResponse {
+    pub user: AdminUserResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUserResponseInner {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub role: Role,
+    pub email_verified: bool,
+    pub totp_enabled: bool,
+    pub suspended_at: Option<CustomDateTime>,
+    pub created_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUserListResponse {
+    pub users: Vec<AdminUserResponseInner>,
+    pub users_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEventResponseInner {
+    pub id: i64,
+    pub actor: Option<AuditActorResponse>,
+    pub action: String,
+    pub target: Option<AuditTargetResponse>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub changes: JsonValue,
+    pub created_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditActorResponse {
+    pub id: Uuid,
+    // None once the user has been deleted
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditTargetResponse {
+    #[serde(rename = "type")]
+    pub target_type: String,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEventListResponse {
+    pub audit_events: Vec<AuditEventResponseInner>,
+    pub audit_events_count: i64,
+}
+
+// Route handlers ↓
+
+pub fn list_users(
+    state: Data<AppState>,
+    (req, params): (HttpRequest, Query<UsersParams>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| {
+            db.send(GetUsers {
+                auth,
+                params: params.into_inner(),
+            })
+            .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn suspend(
+    state: Data<AppState>,
+    (path, req): (Path<UserPath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(SuspendUser { auth, id: path.id }).from_err())
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn unsuspend(
+    state: Data<AppState>,
+    (path, req): (Path<UserPath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(UnsuspendUser { auth, id: path.id }).from_err())
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn force_password_reset(
+    state: Data<AppState>,
+    (path, req): (Path<UserPath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(ForcePasswordReset { auth, id: path.id }).from_err())
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn change_role(
+    state: Data<AppState>,
+    (path, form, req): (Path<UserPath>, Json<In<ChangeRole>>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let change_role = form.into_inner().user;
+
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| {
+            db.send(ChangeRoleOuter {
+                auth,
+                id: path.id,
+                change_role,
+            })
+            .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn delete_user(
+    state: Data<AppState>,
+    (path, req): (Path<UserPath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(DeleteUser { auth, id: path.id }).from_err())
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn list_audit_events(
+    state: Data<AppState>,
+    (req, params): (HttpRequest, Query<AuditParams>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| {
+            db.send(GetAuditEvents {
+                auth,
+                params: params.into_inner(),
+            })
+            .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
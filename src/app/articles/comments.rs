@@ -1,13 +1,17 @@
-use actix_web::{HttpRequest, HttpResponse, web::Json, web::Path, web::Data};
+use actix_web::{
+    http::header::LINK,
+    HttpRequest, HttpResponse, web::Json, web::Path, web::Query, web::Data,
+};
 use actix_http::error::ResponseError;
 use futures::{future::result, Future};
 use validator::Validate;
 
-use super::super::AppState;
+use super::{super::AppState, ActingRoleResponse};
 use crate::app::profiles::ProfileResponseInner;
 use crate::prelude::*;
 use crate::utils::{
-    auth::{authenticate, Auth},
+    auth::{authenticate, Auth, Scope},
+    pagination::{link_header, PageLinks},
     CustomDateTime,
 };
 
@@ -26,6 +30,15 @@ pub struct ArticleCommentPath {
     comment_id: i32,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CommentsParams {
+    pub limit: Option<usize>,   // <- if not set, is 100
+    pub offset: Option<usize>,  // <- if not set, is 0
+    pub after: Option<String>,  // <- cursor from the Link header, instead of offset
+    pub before: Option<String>, // <- cursor from the Link header, instead of offset
+    pub html: Option<bool>,     // <- bodyHtml is only sent when set to true
+}
+
 // Client Messages ↓
 
 #[derive(Debug, Validate, Deserialize)]
@@ -45,6 +58,7 @@ pub struct AddCommentOuter {
 pub struct GetComments {
     pub auth: Option<Auth>,
     pub slug: String,
+    pub params: CommentsParams,
 }
 
 #[derive(Debug)]
@@ -68,12 +82,20 @@ pub struct CommentResponseInner {
     pub created_at: CustomDateTime,
     pub updated_at: CustomDateTime,
     pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_html: Option<String>,
     pub author: ProfileResponseInner,
 }
 
 #[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CommentListResponse {
     pub comments: Vec<CommentResponseInner>,
+    // all the comments on the article, not only the ones on this page
+    pub comments_count: usize,
+    // sent in the Link header
+    #[serde(skip)]
+    pub links: PageLinks,
 }
 
 // Route handlers ↓
@@ -93,6 +115,7 @@ pub fn add(
     result(comment.validate())
         .from_err()
         .and_then(move |_| authenticate(&state, &req))
+        .and_then(|auth| auth.require_scope(Scope::CommentsWrite))
         .and_then(move |auth| {
             db.send(AddCommentOuter {
                 auth,
@@ -102,27 +125,44 @@ pub fn add(
             .from_err()
         })
         .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Ok(mut res) => {
+                res.comment.body_html = None;
+                Ok(HttpResponse::Ok().json(res))
+            }
             Err(e) => Ok(e.error_response()),
         })
 }
 
 pub fn list(
     state: Data<AppState>,
-    (path, req): (Path<ArticlePath>, HttpRequest),
+    (path, params, req): (Path<ArticlePath>, Query<CommentsParams>, HttpRequest),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     let db = state.db.clone();
+    let html = params.html.unwrap_or(false);
 
     authenticate(&state, &req)
         .then(move |auth| {
             db.send(GetComments {
                 auth: auth.ok(),
                 slug: path.slug.to_owned(),
+                params: params.into_inner(),
             })
             .from_err()
         })
-        .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+        .and_then(move |res| match res {
+            Ok(mut res) => {
+                // bodyHtml is only sent to clients that ask for it with ?html=true
+                if !html {
+                    for comment in &mut res.comments {
+                        comment.body_html = None;
+                    }
+                }
+                let mut response = HttpResponse::Ok();
+                if let Some(link) = link_header(req.path(), req.query_string(), &res.links) {
+                    response.header(LINK, link);
+                }
+                Ok(response.json(res))
+            }
             Err(e) => Ok(e.error_response()),
         })
 }
@@ -134,6 +174,7 @@ pub fn delete(
     let db = state.db.clone();
 
     authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::CommentsWrite))
         .and_then(move |auth| {
             db.send(DeleteComment {
                 auth,
@@ -143,7 +184,8 @@ pub fn delete(
             .from_err()
         })
         .and_then(|res| match res {
-            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Ok(Some(acting_role)) => Ok(HttpResponse::Ok().json(ActingRoleResponse { acting_role })),
+            Ok(None) => Ok(HttpResponse::Ok().finish()),
             Err(e) => Ok(e.error_response()),
         })
 }
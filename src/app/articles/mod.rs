@@ -1,15 +1,25 @@
 pub mod comments;
+pub mod revisions;
+pub mod search;
 
-use actix_web::{HttpRequest, HttpResponse, web::Json, web::Path, web::Query, web::Data};
+use actix_web::{
+    http::header::{ETAG, IF_MATCH, IF_NONE_MATCH, VARY},
+    http::header::LINK,
+    HttpRequest, HttpResponse, web::Json, web::Path, web::Query, web::Data,
+};
 use actix_http::error::ResponseError;
 use futures::{future::result, Future};
 use validator::Validate;
 
 use super::AppState;
 use crate::app::profiles::ProfileResponseInner;
+use crate::models::ArticleStatus;
 use crate::prelude::*;
 use crate::utils::{
-    auth::{authenticate, Auth},
+    auth::{authenticate, Auth, Scope},
+    etag::{article_etag, etag_matches},
+    pagination::{link_header, PageLinks},
+    permissions::Role,
     CustomDateTime,
 };
 
@@ -32,12 +42,23 @@ pub struct ArticlesParams {
     pub favorited: Option<String>,
     pub limit: Option<usize>,  // <- if not set, is 20
     pub offset: Option<usize>, // <- if not set, is 0
+    pub after: Option<String>,  // <- cursor from the Link header, instead of offset
+    pub before: Option<String>, // <- cursor from the Link header, instead of offset
+    pub html: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct FeedParams {
     pub limit: Option<usize>,
     pub offset: Option<usize>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub html: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenderParams {
+    pub html: Option<bool>, // <- bodyHtml is only sent when set to true
 }
 
 // Client Messages ↓
@@ -53,6 +74,10 @@ pub struct CreateArticle {
     pub body: String,
     #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
     pub tag_list: Vec<String>,
+    // draft, published or unlisted, published if not set unless publishAt is
+    pub status: Option<String>,
+    // when to publish a draft or unlisted article, e.g. 2019-06-01T12:00:00Z
+    pub publish_at: Option<String>,
 }
 
 #[derive(Debug)]
@@ -78,6 +103,9 @@ pub struct UpdateArticle {
     pub body: Option<String>,
     #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
     pub tag_list: Option<Vec<String>>,
+    // setting the status without a publishAt cancels the schedule
+    pub status: Option<String>,
+    pub publish_at: Option<String>,
 }
 
 #[derive(Debug)]
@@ -85,6 +113,8 @@ pub struct UpdateArticleOuter {
     pub auth: Auth,
     pub slug: String,
     pub article: UpdateArticle,
+    // the update only goes through while the article still has one of these ETags
+    pub if_match: Option<String>,
 }
 
 #[derive(Debug)]
@@ -93,6 +123,12 @@ pub struct DeleteArticle {
     pub slug: String,
 }
 
+#[derive(Debug)]
+pub struct PublishArticle {
+    pub auth: Auth,
+    pub slug: String,
+}
+
 #[derive(Debug)]
 pub struct FavoriteArticle {
     pub auth: Auth,
@@ -117,11 +153,31 @@ pub struct GetFeed {
     pub params: FeedParams,
 }
 
+#[derive(Debug)]
+pub struct GetDrafts {
+    pub auth: Auth,
+    pub params: FeedParams,
+}
+
 // JSON response objects ↓
 
 #[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ArticleResponse {
     pub article: ArticleResponseInner,
+    // set when a moderator or admin acted on someone else's article
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acting_role: Option<Role>,
+    // sent as the ETag header
+    #[serde(skip)]
+    pub version: i32,
+}
+
+// Returned instead of an empty body when a moderator or admin removed someone else's content
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActingRoleResponse {
+    pub acting_role: Role,
 }
 
 #[derive(Debug, Serialize)]
@@ -131,9 +187,17 @@ pub struct ArticleResponseInner {
     pub title: String,
     pub description: String,
     pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_html: Option<String>,
+    // the best matching part of the description and body, for search results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
     pub tag_list: Vec<String>,
+    pub status: ArticleStatus,
     pub created_at: CustomDateTime,
     pub updated_at: CustomDateTime,
+    pub published_at: Option<CustomDateTime>,
+    pub publish_at: Option<CustomDateTime>,
     pub favorited: bool,
     pub favorites_count: usize,
     pub author: ProfileResponseInner,
@@ -143,7 +207,11 @@ pub struct ArticleResponseInner {
 #[serde(rename_all = "camelCase")]
 pub struct ArticleListResponse {
     pub articles: Vec<ArticleResponseInner>,
+    // all the articles that match, not only the ones on this page
     pub articles_count: usize,
+    // sent in the Link header
+    #[serde(skip)]
+    pub links: PageLinks,
 }
 
 // Route handlers ↓
@@ -158,18 +226,21 @@ pub fn create(
     result(article.validate())
         .from_err()
         .and_then(move |_| authenticate(&state, &req))
+        .and_then(|auth| auth.require_scope(Scope::ArticlesWrite))
         .and_then(move |auth| db.send(CreateArticleOuter { auth, article }).from_err())
         .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Ok(res) => Ok(respond_with_article(res, false)),
             Err(e) => Ok(e.error_response()),
         })
 }
 
 pub fn get(
     state: Data<AppState>,
-    (path, req): (Path<ArticlePath>, HttpRequest),
+    (path, params, req): (Path<ArticlePath>, Query<RenderParams>, HttpRequest),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     let db = state.db.clone();
+    let if_none_match = header_value(&req, IF_NONE_MATCH);
+    let html = params.html.unwrap_or(false);
 
     authenticate(&state, &req)
         .then(move |auth| {
@@ -179,8 +250,8 @@ pub fn get(
             })
             .from_err()
         })
-        .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+        .and_then(move |res| match res {
+            Ok(res) => Ok(respond_with_article_unless_cached(res, html, if_none_match)),
             Err(e) => Ok(e.error_response()),
         })
 }
@@ -194,22 +265,25 @@ pub fn update(
     ),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     let article = form.into_inner().article;
+    let if_match = header_value(&req, IF_MATCH);
 
     let db = state.db.clone();
 
     result(article.validate())
         .from_err()
         .and_then(move |_| authenticate(&state, &req))
+        .and_then(|auth| auth.require_scope(Scope::ArticlesWrite))
         .and_then(move |auth| {
             db.send(UpdateArticleOuter {
                 auth,
                 slug: path.slug.to_owned(),
                 article,
+                if_match,
             })
             .from_err()
         })
         .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Ok(res) => Ok(respond_with_article(res, false)),
             Err(e) => Ok(e.error_response()),
         })
 }
@@ -219,6 +293,7 @@ pub fn delete(
     (path, req): (Path<ArticlePath>, HttpRequest),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::ArticlesWrite))
         .and_then(move |auth| {
             state
                 .db
@@ -229,7 +304,29 @@ pub fn delete(
                 .from_err()
         })
         .and_then(|res| match res {
-            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Ok(Some(acting_role)) => Ok(HttpResponse::Ok().json(ActingRoleResponse { acting_role })),
+            Ok(None) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn publish(
+    state: Data<AppState>,
+    (path, req): (Path<ArticlePath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::ArticlesWrite))
+        .and_then(move |auth| {
+            state
+                .db
+                .send(PublishArticle {
+                    auth,
+                    slug: path.slug.to_owned(),
+                })
+                .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(res) => Ok(respond_with_article(res, false)),
             Err(e) => Ok(e.error_response()),
         })
 }
@@ -239,6 +336,7 @@ pub fn favorite(
     (path, req): (Path<ArticlePath>, HttpRequest),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::ArticlesWrite))
         .and_then(move |auth| {
             state
                 .db
@@ -249,7 +347,7 @@ pub fn favorite(
                 .from_err()
         })
         .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Ok(res) => Ok(respond_with_article(res, false)),
             Err(e) => Ok(e.error_response()),
         })
 }
@@ -259,6 +357,7 @@ pub fn unfavorite(
     (path, req): (Path<ArticlePath>, HttpRequest),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::ArticlesWrite))
         .and_then(move |auth| {
             state
                 .db
@@ -269,7 +368,7 @@ pub fn unfavorite(
                 .from_err()
         })
         .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Ok(res) => Ok(respond_with_article(res, false)),
             Err(e) => Ok(e.error_response()),
         })
 }
@@ -279,6 +378,7 @@ pub fn list(
     (req, params): (HttpRequest, Query<ArticlesParams>),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     let db = state.db.clone();
+    let html = params.html.unwrap_or(false);
 
     authenticate(&state, &req)
         .then(move |auth| {
@@ -288,8 +388,8 @@ pub fn list(
             })
             .from_err()
         })
-        .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+        .and_then(move |res| match res {
+            Ok(res) => Ok(respond_with_list(&req, with_body_html(res, html))),
             Err(e) => Ok(e.error_response()),
         })
 }
@@ -299,8 +399,10 @@ pub fn feed(
     (req, params): (HttpRequest, Query<FeedParams>),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     let db = state.db.clone();
+    let html = params.html.unwrap_or(false);
 
     authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::Read))
         .and_then(move |auth| {
             db.send(GetFeed {
                 auth,
@@ -308,8 +410,92 @@ pub fn feed(
             })
             .from_err()
         })
-        .and_then(|res| match res {
-            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+        .and_then(move |res| match res {
+            Ok(res) => Ok(respond_with_list(&req, with_body_html(res, html))),
             Err(e) => Ok(e.error_response()),
         })
 }
+
+pub fn drafts(
+    state: Data<AppState>,
+    (req, params): (HttpRequest, Query<FeedParams>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+    let html = params.html.unwrap_or(false);
+
+    authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::Read))
+        .and_then(move |auth| {
+            db.send(GetDrafts {
+                auth,
+                params: params.into_inner(),
+            })
+            .from_err()
+        })
+        .and_then(move |res| match res {
+            Ok(res) => Ok(respond_with_list(&req, with_body_html(res, html))),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+// helper methods ↓
+
+// Single articles carry their ETag, so they can be updated with If-Match
+fn respond_with_article(article: ArticleResponse, html: bool) -> HttpResponse {
+    respond_with_article_unless_cached(article, html, None)
+}
+
+// The ETag is taken over the body as it's sent, which differs by who's asking, so caches have to
+// keep the copies of different readers apart
+fn respond_with_article_unless_cached(
+    mut article: ArticleResponse,
+    html: bool,
+    if_none_match: Option<String>,
+) -> HttpResponse {
+    if !html {
+        article.article.body_html = None;
+    }
+    let body = match serde_json::to_vec(&article) {
+        Ok(body) => body,
+        Err(_) => return Error::InternalServerError.error_response(),
+    };
+    let etag = article_etag(article.version, &body);
+
+    match if_none_match {
+        Some(ref header) if etag_matches(header, &etag) => HttpResponse::NotModified()
+            .header(ETAG, etag)
+            .header(VARY, "Authorization")
+            .finish(),
+        _ => HttpResponse::Ok()
+            .header(ETAG, etag)
+            .header(VARY, "Authorization")
+            .content_type("application/json")
+            .body(body),
+    }
+}
+
+fn header_value(req: &HttpRequest, name: actix_web::http::header::HeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+// Lists link to the pages before and after them
+fn respond_with_list(req: &HttpRequest, list: ArticleListResponse) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    if let Some(link) = link_header(req.path(), req.query_string(), &list.links) {
+        response.header(LINK, link);
+    }
+    response.json(list)
+}
+
+// bodyHtml is only sent to clients that ask for it with ?html=true
+fn with_body_html(mut list: ArticleListResponse, html: bool) -> ArticleListResponse {
+    if !html {
+        for article in &mut list.articles {
+            article.body_html = None;
+        }
+    }
+    list
+}
@@ -0,0 +1,185 @@
+use actix_web::{HttpRequest, HttpResponse, web::Path, web::Query, web::Data};
+use actix_http::error::ResponseError;
+use futures::Future;
+
+use super::{super::AppState, respond_with_article};
+use crate::prelude::*;
+use crate::utils::{
+    auth::{authenticate, Auth, Scope},
+    diff::DiffLine,
+    CustomDateTime,
+};
+
+// Extractors ↓
+
+use super::ArticlePath;
+
+#[derive(Debug, Deserialize)]
+pub struct ArticleRevisionPath {
+    slug: String,
+    number: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffParams {
+    pub to: Option<i32>, // <- if not set, the article as it is now
+}
+
+// Client Messages ↓
+
+#[derive(Debug)]
+pub struct GetRevisions {
+    pub auth: Auth,
+    pub slug: String,
+}
+
+#[derive(Debug)]
+pub struct GetRevision {
+    pub auth: Auth,
+    pub slug: String,
+    pub number: i32,
+}
+
+#[derive(Debug)]
+pub struct GetRevisionDiff {
+    pub auth: Auth,
+    pub slug: String,
+    pub number: i32,
+    pub params: DiffParams,
+}
+
+#[derive(Debug)]
+pub struct RestoreRevision {
+    pub auth: Auth,
+    pub slug: String,
+    pub number: i32,
+}
+
+// JSON response objects ↓
+
+#[derive(Debug, Serialize)]
+pub struct RevisionResponse {
+    pub revision: RevisionResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionResponseInner {
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    // when this version was replaced by the next one
+    pub created_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionListResponse {
+    pub revisions: Vec<RevisionResponseInner>,
+    pub revisions_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffResponse {
+    pub diff: DiffResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffResponseInner {
+    pub from: i32,
+    pub to: Option<i32>,
+    pub title: Vec<DiffLine>,
+    pub description: Vec<DiffLine>,
+    pub body: Vec<DiffLine>,
+}
+
+// Route handlers ↓
+
+pub fn list(
+    state: Data<AppState>,
+    (path, req): (Path<ArticlePath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::Read))
+        .and_then(move |auth| {
+            db.send(GetRevisions {
+                auth,
+                slug: path.slug.to_owned(),
+            })
+            .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn get(
+    state: Data<AppState>,
+    (path, req): (Path<ArticleRevisionPath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::Read))
+        .and_then(move |auth| {
+            db.send(GetRevision {
+                auth,
+                slug: path.slug.to_owned(),
+                number: path.number,
+            })
+            .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn diff(
+    state: Data<AppState>,
+    (path, params, req): (Path<ArticleRevisionPath>, Query<DiffParams>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::Read))
+        .and_then(move |auth| {
+            db.send(GetRevisionDiff {
+                auth,
+                slug: path.slug.to_owned(),
+                number: path.number,
+                params: params.into_inner(),
+            })
+            .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn restore(
+    state: Data<AppState>,
+    (path, req): (Path<ArticleRevisionPath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::ArticlesWrite))
+        .and_then(move |auth| {
+            db.send(RestoreRevision {
+                auth,
+                slug: path.slug.to_owned(),
+                number: path.number,
+            })
+            .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(res) => Ok(respond_with_article(res, false)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
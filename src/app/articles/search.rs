@@ -0,0 +1,48 @@
+use actix_web::{HttpRequest, HttpResponse, web::Query, web::Data};
+use actix_http::error::ResponseError;
+use futures::Future;
+
+use super::{super::AppState, with_body_html};
+use crate::prelude::*;
+use crate::utils::auth::{authenticate, Auth};
+
+// Extractors ↓
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    pub q: Option<String>, // <- words, "quoted phrases", or and -excluded words
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub html: Option<bool>,
+}
+
+// Client Messages ↓
+
+#[derive(Debug)]
+pub struct SearchArticles {
+    pub auth: Option<Auth>,
+    pub params: SearchParams,
+}
+
+// Route handlers ↓
+
+pub fn search(
+    state: Data<AppState>,
+    (req, params): (HttpRequest, Query<SearchParams>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+    let html = params.html.unwrap_or(false);
+
+    authenticate(&state, &req)
+        .then(move |auth| {
+            db.send(SearchArticles {
+                auth: auth.ok(),
+                params: params.into_inner(),
+            })
+            .from_err()
+        })
+        .and_then(move |res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(with_body_html(res, html))),
+            Err(e) => Ok(e.error_response()),
+        })
+}
@@ -0,0 +1,165 @@
+use actix_web::{
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    web::{Bytes, Data},
+    HttpRequest, HttpResponse,
+};
+use actix_http::error::ResponseError;
+use futures::{stream, Future};
+use uuid::Uuid;
+
+use super::AppState;
+use crate::prelude::*;
+use crate::utils::{
+    auth::{authenticate, Auth},
+    permissions::Role,
+    CustomDateTime,
+};
+
+// Client Messages ↓
+
+#[derive(Debug)]
+pub struct ExportData {
+    pub auth: Auth,
+}
+
+// JSON response objects ↓
+
+// One line of the export, e.g. {"type":"article","data":{...}}
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum ExportRecord {
+    User(ExportUser),
+    Session(ExportSession),
+    PersonalAccessToken(ExportPersonalAccessToken),
+    Identity(ExportIdentity),
+    Article(ExportArticle),
+    ArticleRevision(ExportArticleRevision),
+    Comment(ExportComment),
+    Favorite(ExportFavorite),
+    Follower(ExportFollow),
+    Following(ExportFollow),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportUser {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub role: Role,
+    pub email_verified_at: Option<CustomDateTime>,
+    pub totp_enabled_at: Option<CustomDateTime>,
+    pub created_at: CustomDateTime,
+    pub updated_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSession {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: CustomDateTime,
+    pub last_seen_at: CustomDateTime,
+    pub expires_at: CustomDateTime,
+    pub revoked_at: Option<CustomDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPersonalAccessToken {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: CustomDateTime,
+    pub last_used_at: Option<CustomDateTime>,
+    pub expires_at: Option<CustomDateTime>,
+    pub revoked_at: Option<CustomDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportArticle {
+    pub id: Uuid,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub tag_list: Vec<String>,
+    pub status: String,
+    pub created_at: CustomDateTime,
+    pub updated_at: CustomDateTime,
+    pub published_at: Option<CustomDateTime>,
+    pub publish_at: Option<CustomDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportArticleRevision {
+    pub article_id: Uuid,
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub created_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportComment {
+    pub id: i32,
+    pub article_slug: String,
+    pub body: String,
+    pub created_at: CustomDateTime,
+    pub updated_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFavorite {
+    pub article_slug: String,
+    pub created_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFollow {
+    pub username: String,
+    pub created_at: CustomDateTime,
+}
+
+// Route handlers ↓
+
+pub fn export(state: Data<AppState>, req: HttpRequest) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(ExportData { auth }).from_err())
+        .and_then(|res| match res {
+            Ok(records) => {
+                // JSON lines, so the archive can be processed one record at a time
+                let lines = records.into_iter().map(|record| {
+                    let mut line = serde_json::to_vec(&record)?;
+                    line.push(b'\n');
+                    Ok(Bytes::from(line))
+                });
+                Ok(HttpResponse::Ok()
+                    .header(CONTENT_TYPE, "application/x-ndjson")
+                    .header(CONTENT_DISPOSITION, "attachment; filename=\"conduit-export.jsonl\"")
+                    .streaming(stream::iter_result::<_, _, serde_json::Error>(lines)))
+            }
+            Err(e) => Ok(e.error_response()),
+        })
+}
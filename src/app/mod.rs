@@ -1,21 +1,31 @@
 use crate::db::{new_pool, DbExecutor};
-use actix::prelude::{Addr, SyncArbiter};
+use crate::utils::jwt;
+use actix::prelude::{Actor, Addr, SyncArbiter};
 use actix_web::{
     middleware::Logger,
     web::Data,
     web,
-    App, HttpRequest,
+    App, HttpRequest, HttpResponse,
     HttpServer,
-    http::header::{AUTHORIZATION, CONTENT_TYPE},
+    http::header::{AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, LINK},
 };
 use actix_cors::Cors;
 use std::env;
 
+pub mod admin;
 pub mod articles;
+pub mod export;
+pub mod oidc;
 pub mod profiles;
+pub mod scheduler;
+pub mod sessions;
 pub mod tags;
+pub mod tokens;
 pub mod users;
 
+#[cfg(test)]
+mod tests;
+
 pub struct AppState {
     pub db: Addr<DbExecutor>,
 }
@@ -24,13 +34,26 @@ fn index(_state: Data<AppState>, _req: HttpRequest) -> &'static str {
     "Hello world!"
 }
 
+fn jwks(_req: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok().json(jwt::jwks())
+}
+
 pub fn start() {
+    jwt::check_configuration();
+    crate::utils::hasher::check_configuration();
+    crate::utils::mailer::check_configuration();
+    crate::utils::oidc::check_configuration();
+    crate::utils::password_policy::check_configuration();
+    crate::utils::proxies::check_configuration();
+
     let frontend_origin = env::var("FRONTEND_ORIGIN").ok();
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let database_pool = new_pool(database_url).expect("Failed to create pool.");
     let database_address = SyncArbiter::start(num_cpus::get(), move || DbExecutor(database_pool.clone()));
 
+    scheduler::Scheduler::new(database_address.clone()).start();
+
     let bind_address = env::var("BIND_ADDRESS").expect("BIND_ADDRESS is not set");
 
     HttpServer::new(move || {
@@ -40,12 +63,14 @@ pub fn start() {
         let cors = match frontend_origin {
             Some(ref origin) => Cors::new()
                 .allowed_origin(origin)
-                .allowed_headers(vec![AUTHORIZATION, CONTENT_TYPE])
+                .allowed_headers(vec![AUTHORIZATION, CONTENT_TYPE, IF_MATCH, IF_NONE_MATCH])
+                .expose_headers(vec![ETAG, LINK])
                 .max_age(3600),
             None => Cors::new()
                 .allowed_origin("*")
                 .send_wildcard()
-                .allowed_headers(vec![AUTHORIZATION, CONTENT_TYPE])
+                .allowed_headers(vec![AUTHORIZATION, CONTENT_TYPE, IF_MATCH, IF_NONE_MATCH])
+                .expose_headers(vec![ETAG, LINK])
                 .max_age(3600),
         };
         App::new()
@@ -64,6 +89,7 @@ pub fn start() {
 fn routes(app: &mut web::ServiceConfig) {
     app
         .service(web::resource("/").to(index))
+        .service(web::resource("/.well-known/jwks.json").to(jwks))
         .service(web::scope("/api")
                 // User routes ↓
                 .service(web::resource("users")
@@ -72,9 +98,61 @@ fn routes(app: &mut web::ServiceConfig) {
                 .service(web::resource("users/login")
                     .route(web::post().to_async(users::login))
                 )
+                .service(web::resource("users/login/totp")
+                    .route(web::post().to_async(users::login_totp))
+                )
+                .service(web::resource("users/refresh")
+                    .route(web::post().to_async(users::refresh))
+                )
+                .service(web::resource("users/logout")
+                    .route(web::post().to_async(users::logout))
+                )
+                .service(web::resource("users/verify-email")
+                    .route(web::post().to_async(users::verify_email))
+                )
+                .service(web::resource("users/password-reset")
+                    .route(web::post().to_async(users::request_password_reset))
+                )
+                .service(web::resource("users/password-reset/confirm")
+                    .route(web::post().to_async(users::confirm_password_reset))
+                )
                 .service(web::resource("user")
                     .route(web::get().to_async(users::get_current))
                     .route(web::put().to_async(users::update))
+                    .route(web::delete().to_async(users::delete))
+                )
+                .service(web::resource("user/export")
+                    .route(web::get().to_async(export::export))
+                )
+                .service(web::resource("user/verify-email")
+                    .route(web::post().to_async(users::resend_email_verification))
+                )
+                .service(web::resource("user/sessions")
+                    .route(web::get().to_async(sessions::list))
+                    .route(web::delete().to_async(sessions::revoke_all))
+                )
+                .service(web::resource("user/sessions/{id}")
+                    .route(web::delete().to_async(sessions::revoke))
+                )
+                .service(web::resource("user/tokens")
+                    .route(web::get().to_async(tokens::list))
+                    .route(web::post().to_async(tokens::create))
+                )
+                .service(web::resource("user/tokens/{id}")
+                    .route(web::delete().to_async(tokens::revoke))
+                )
+                .service(web::resource("user/identities")
+                    .route(web::post().to_async(oidc::confirm_link))
+                )
+                .service(web::resource("user/drafts")
+                    .route(web::get().to_async(articles::drafts))
+                )
+                // Sign-in with identity providers ↓
+                .service(web::resource("auth/oidc/{provider}/start")
+                    .route(web::get().to_async(oidc::start))
+                )
+                .service(web::resource("auth/oidc/{provider}/callback")
+                    .route(web::get().to_async(oidc::callback))
                 )
                 // Profile routes ↓
                 .service(web::resource("profiles/{username}")
@@ -92,11 +170,17 @@ fn routes(app: &mut web::ServiceConfig) {
                 .service(web::resource("articles/feed")
                     .route(web::get().to_async(articles::feed))
                 )
+                .service(web::resource("articles/search")
+                    .route(web::get().to_async(articles::search::search))
+                )
                 .service(web::resource("articles/{slug}")
                     .route(web::get().to_async(articles::get))
                     .route(web::put().to_async(articles::update))
                     .route(web::delete().to_async(articles::delete))
                 )
+                .service(web::resource("articles/{slug}/publish")
+                    .route(web::post().to_async(articles::publish))
+                )
                 .service(web::resource("articles/{slug}/favorite")
                     .route(web::post().to_async(articles::favorite))
                     .route(web::delete().to_async(articles::unfavorite))
@@ -108,6 +192,38 @@ fn routes(app: &mut web::ServiceConfig) {
                 .service(web::resource("articles/{slug}/comments/{comment_id}")
                     .route(web::delete().to_async(articles::comments::delete))
                 )
+                .service(web::resource("articles/{slug}/revisions")
+                    .route(web::get().to_async(articles::revisions::list))
+                )
+                .service(web::resource("articles/{slug}/revisions/{number}")
+                    .route(web::get().to_async(articles::revisions::get))
+                )
+                .service(web::resource("articles/{slug}/revisions/{number}/diff")
+                    .route(web::get().to_async(articles::revisions::diff))
+                )
+                .service(web::resource("articles/{slug}/revisions/{number}/restore")
+                    .route(web::post().to_async(articles::revisions::restore))
+                )
+                // Admin routes ↓
+                .service(web::resource("admin/users")
+                    .route(web::get().to_async(admin::list_users))
+                )
+                .service(web::resource("admin/users/{id}")
+                    .route(web::delete().to_async(admin::delete_user))
+                )
+                .service(web::resource("admin/users/{id}/suspension")
+                    .route(web::put().to_async(admin::suspend))
+                    .route(web::delete().to_async(admin::unsuspend))
+                )
+                .service(web::resource("admin/users/{id}/password-reset")
+                    .route(web::post().to_async(admin::force_password_reset))
+                )
+                .service(web::resource("admin/users/{id}/role")
+                    .route(web::put().to_async(admin::change_role))
+                )
+                .service(web::resource("admin/audit")
+                    .route(web::get().to_async(admin::list_audit_events))
+                )
                 // Tags routes ↓
                 .service(web::resource("tags")
                     .route(web::get().to_async(tags::get))
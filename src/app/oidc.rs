@@ -0,0 +1,245 @@
+use actix_web::{
+    http::header::LOCATION,
+    web::{Data, Json, Path, Query},
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use actix_http::{
+    cookie::{Cookie, SameSite},
+    error::ResponseError,
+};
+use futures::{future::result, Future};
+use validator::Validate;
+
+use super::{users::LoginResponse, AppState};
+use crate::prelude::*;
+use crate::utils::{
+    auth::{authenticate, Auth, ClientInfo},
+    oidc::{provider, IdTokenClaims, OidcProvider, LOGIN_LIFETIME_MINUTES},
+    CustomDateTime,
+};
+
+// Holds a random value only the browser that started a sign-in knows, its hash is stored with the
+// sign-in. Without it anyone could have someone else's browser finish their sign-in for them.
+const BINDING_COOKIE: &str = "conduit_oidc_binding";
+
+// Extractors ↓
+
+#[derive(Debug, Deserialize)]
+pub struct In<T> {
+    identity: T,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProviderPath {
+    provider: String,
+}
+
+// What the provider appends to our redirect URI
+#[derive(Debug, Deserialize)]
+pub struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+// Client Messages ↓
+
+#[derive(Debug)]
+pub struct StartOidcLogin {
+    pub provider: String,
+}
+
+#[derive(Debug)]
+pub struct ConsumeOidcLogin {
+    pub provider: String,
+    pub state: String,
+    // from the cookie the sign-in was started with
+    pub binding: String,
+}
+
+// Everything needed to finish a sign-in once the provider sends the user back
+#[derive(Debug)]
+pub struct OidcLogin {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub binding: String,
+}
+
+#[derive(Debug)]
+pub struct OidcSignIn {
+    pub provider: String,
+    pub claims: IdTokenClaims,
+    pub client: ClientInfo,
+}
+
+// The owner of an account proves it's theirs the same way as when logging in, before an identity
+// that only shares its email address can sign into it
+#[derive(Debug, Validate, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfirmLink {
+    #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
+    pub link_token: String,
+    #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
+    pub password: String,
+    // required when the account has two-factor authentication enabled, a recovery code works too
+    pub code: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ConfirmLinkOuter {
+    pub auth: Auth,
+    pub confirm_link: ConfirmLink,
+}
+
+// JSON response objects ↓
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum OidcSignInResponse {
+    SignedIn(LoginResponse),
+    LinkRequired(LinkResponse),
+}
+
+// Returned instead of signing in when the provider's email belongs to an account that isn't
+// linked yet. Its owner signs in as usual and confirms the link with the token.
+#[derive(Debug, Serialize)]
+pub struct LinkResponse {
+    pub link: LinkResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkResponseInner {
+    pub token: String,
+    pub provider: String,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IdentityResponse {
+    pub identity: IdentityResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityResponseInner {
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_at: CustomDateTime,
+}
+
+// Route handlers ↓
+
+pub fn start(
+    state: Data<AppState>,
+    path: Path<ProviderPath>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    result(provider(&path.provider))
+        .and_then(move |provider| {
+            db.send(StartOidcLogin {
+                provider: provider.name.to_owned(),
+            })
+            .from_err()
+            .and_then(|res| res)
+            .and_then(move |login| {
+                let cookie = binding_cookie(provider, login.binding);
+                provider
+                    .authorization_url(login.state, login.nonce, &login.code_verifier)
+                    .map(move |url| (url, cookie))
+            })
+        })
+        .then(|res| match res {
+            Ok((url, cookie)) => Ok(HttpResponse::Found().header(LOCATION, url).cookie(cookie).finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn callback(
+    state: Data<AppState>,
+    (path, params, req): (Path<ProviderPath>, Query<CallbackParams>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+    let client = ClientInfo::from_request(&req);
+    let params = params.into_inner();
+    let binding = req.cookie(BINDING_COOKIE).map(|cookie| cookie.value().to_owned());
+
+    let callback_params = match (params.code, params.state, params.error, binding) {
+        (Some(code), Some(state), None, Some(binding)) => Ok((code, state, binding)),
+        // e.g. the user declined to sign in
+        (_, _, Some(error), _) => Err(Error::Unauthorized(json!({
+            "error": format!("The identity provider refused the sign-in: {}", error),
+        }))),
+        (Some(_), Some(_), None, None) => Err(Error::Unauthorized(json!({
+            "error": "This sign-in wasn't started in this browser, please start over",
+        }))),
+        _ => Err(Error::UnprocessableEntity(json!({
+            "error": "code and state are required",
+        }))),
+    };
+
+    result(provider(&path.provider))
+        .and_then(move |provider| result(callback_params).map(move |params| (provider, params)))
+        .and_then(move |(provider, (code, state, binding))| {
+            let sign_in_db = db.clone();
+
+            db.send(ConsumeOidcLogin {
+                provider: provider.name.to_owned(),
+                state,
+                binding,
+            })
+            .from_err()
+            .and_then(|res| res)
+            .and_then(move |login| provider.sign_in(code, login.code_verifier, login.nonce))
+            .and_then(move |claims| {
+                sign_in_db
+                    .send(OidcSignIn {
+                        provider: provider.name.to_owned(),
+                        claims,
+                        client,
+                    })
+                    .from_err()
+                    .and_then(|res| res)
+            })
+        })
+        .then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok()
+                .del_cookie(&Cookie::build(BINDING_COOKIE, "").path("/").finish())
+                .json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn confirm_link(
+    state: Data<AppState>,
+    (form, req): (Json<In<ConfirmLink>>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let confirm_link = form.into_inner().identity;
+
+    let db = state.db.clone();
+
+    result(confirm_link.validate())
+        .from_err()
+        .and_then(move |_| authenticate(&state, &req))
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(ConfirmLinkOuter { auth, confirm_link }).from_err())
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+// local helper methods ↓
+
+// Sent along with the top-level navigation back from the provider, but not with requests other
+// sites make in the background
+fn binding_cookie(provider: &OidcProvider, binding: String) -> Cookie<'static> {
+    Cookie::build(BINDING_COOKIE, binding)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(provider.secure_callback())
+        .max_age(LOGIN_LIFETIME_MINUTES * 60)
+        .finish()
+}
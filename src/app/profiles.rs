@@ -4,7 +4,7 @@ use futures::Future;
 
 use super::AppState;
 use crate::prelude::*;
-use crate::utils::auth::{authenticate, Auth};
+use crate::utils::auth::{authenticate, Auth, Scope};
 
 // Extractors ↓
 
@@ -78,6 +78,7 @@ pub fn follow(
     let db = state.db.clone();
 
     authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::ProfilesWrite))
         .and_then(move |auth| {
             db.send(FollowProfile {
                 auth,
@@ -98,6 +99,7 @@ pub fn unfollow(
     let db = state.db.clone();
 
     authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::ProfilesWrite))
         .and_then(move |auth| {
             db.send(UnfollowProfile {
                 auth,
@@ -0,0 +1,63 @@
+use actix::prelude::*;
+use std::{env, time::Duration};
+
+use crate::db::DbExecutor;
+
+const DEFAULT_INTERVAL_SECS: u64 = 30;
+
+// Publishes articles once their publishAt has passed. The schedule only lives in the database,
+// so nothing is lost on a restart, and every server instance can run its own scheduler since
+// DbExecutor skips articles another instance is already publishing.
+pub struct Scheduler {
+    db: Addr<DbExecutor>,
+    interval: Duration,
+}
+
+#[derive(Debug)]
+pub struct PublishDueArticles;
+
+impl Scheduler {
+    // Panics on an invalid SCHEDULER_INTERVAL_SECS, so create it before the server starts
+    pub fn new(db: Addr<DbExecutor>) -> Self {
+        let interval_secs = match env::var("SCHEDULER_INTERVAL_SECS") {
+            Ok(secs) => match secs.parse::<u64>() {
+                Ok(secs) if secs > 0 => secs,
+                _ => panic!("SCHEDULER_INTERVAL_SECS must be a positive number, not {}", secs),
+            },
+            Err(_) => DEFAULT_INTERVAL_SECS,
+        };
+
+        Scheduler {
+            db,
+            interval: Duration::from_secs(interval_secs),
+        }
+    }
+
+    fn publish_due_articles(&mut self, ctx: &mut Context<Self>) {
+        let publishing = self
+            .db
+            .send(PublishDueArticles)
+            .into_actor(self)
+            .map(|res, _, _| match res {
+                Ok(slugs) => {
+                    for slug in slugs {
+                        log::info!("Published scheduled article {}", slug);
+                    }
+                }
+                Err(e) => log::error!("Publishing scheduled articles failed: {}", e),
+            })
+            .map_err(|e, _, _| log::error!("Publishing scheduled articles failed: {}", e));
+
+        ctx.spawn(publishing);
+    }
+}
+
+impl Actor for Scheduler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // catch up on whatever fell due while the server was down
+        self.publish_due_articles(ctx);
+        ctx.run_interval(self.interval, Self::publish_due_articles);
+    }
+}
@@ -0,0 +1,102 @@
+use actix_web::{HttpRequest, HttpResponse, web::Path, web::Data};
+use actix_http::error::ResponseError;
+use futures::Future;
+use uuid::Uuid;
+
+use super::AppState;
+use crate::prelude::*;
+use crate::utils::{
+    auth::{authenticate, Auth},
+    CustomDateTime,
+};
+
+// Extractors ↓
+
+#[derive(Debug, Deserialize)]
+pub struct SessionPath {
+    id: Uuid,
+}
+
+// Client Messages ↓
+
+#[derive(Debug)]
+pub struct GetSessions {
+    pub auth: Auth,
+}
+
+#[derive(Debug)]
+pub struct RevokeSession {
+    pub auth: Auth,
+    pub session_id: Uuid,
+}
+
+#[derive(Debug)]
+pub struct RevokeAllSessions {
+    pub auth: Auth,
+}
+
+// JSON response objects ↓
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResponseInner {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: CustomDateTime,
+    pub last_seen_at: CustomDateTime,
+    // whether this is the session making the request
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionListResponse {
+    pub sessions: Vec<SessionResponseInner>,
+}
+
+// Route handlers ↓
+
+pub fn list(state: Data<AppState>, req: HttpRequest) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(GetSessions { auth }).from_err())
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn revoke(
+    state: Data<AppState>,
+    (path, req): (Path<SessionPath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| {
+            db.send(RevokeSession {
+                auth,
+                session_id: path.id,
+            })
+            .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn revoke_all(state: Data<AppState>, req: HttpRequest) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(RevokeAllSessions { auth }).from_err())
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
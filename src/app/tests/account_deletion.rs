@@ -0,0 +1,419 @@
+use actix_web::{http::StatusCode, test};
+use diesel::prelude::*;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{authorized, call, connection, register, TestUser, PASSWORD};
+
+// What a user leaves around: an article with a revision, a comment, a favorite and a follow on
+// someone else's, a personal access token and an identity, and the same coming back from the other
+struct Footprint {
+    article_id: Uuid,
+    article_slug: String,
+    other_article_slug: String,
+    session_ids: Vec<Uuid>,
+}
+
+fn leave_footprint(user: &TestUser, other: &TestUser) -> Footprint {
+    use crate::models::NewIdentity;
+    use crate::schema::{identities, sessions};
+
+    let article_slug = create_article(user);
+    let other_article_slug = create_article(other);
+
+    let response = call(
+        authorized(test::TestRequest::put(), &user.token)
+            .uri(&format!("/api/articles/{}", article_slug))
+            .set_json(&json!({ "article": { "body": "Changed, so there's a revision" } })),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    for (author, slug) in &[(user, &other_article_slug), (other, &article_slug)] {
+        let response = call(
+            authorized(test::TestRequest::post(), &author.token)
+                .uri(&format!("/api/articles/{}/comments", slug))
+                .set_json(&json!({ "comment": { "body": format!("From {}", author.username) } })),
+        );
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        let response = call(
+            authorized(test::TestRequest::post(), &author.token)
+                .uri(&format!("/api/articles/{}/favorite", slug)),
+        );
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    for (follower, followed) in &[(user, other), (other, user)] {
+        let response = call(
+            authorized(test::TestRequest::post(), &follower.token)
+                .uri(&format!("/api/profiles/{}/follow", followed.username)),
+        );
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    let response = call(
+        authorized(test::TestRequest::post(), &user.token)
+            .uri("/api/user/tokens")
+            .set_json(&json!({ "token": { "name": "ci", "scopes": ["read"] } })),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let conn = connection();
+    diesel::insert_into(identities::table)
+        .values(NewIdentity {
+            user_id: user.id,
+            provider: "test".into(),
+            subject: user.id.to_string(),
+            email: Some(user.email.clone()),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    Footprint {
+        article_id: article_id(&article_slug),
+        article_slug,
+        other_article_slug,
+        session_ids: sessions::table
+            .filter(sessions::user_id.eq(user.id))
+            .select(sessions::id)
+            .load(&conn)
+            .unwrap(),
+    }
+}
+
+fn create_article(author: &TestUser) -> String {
+    let response = call(
+        authorized(test::TestRequest::post(), &author.token)
+            .uri("/api/articles")
+            .set_json(&json!({
+                "article": {
+                    "title": format!("By {}", author.username),
+                    "description": "About something",
+                    "body": "Something",
+                    "tagList": ["deletion"],
+                },
+            })),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    response.body["article"]["slug"]
+        .as_str()
+        .unwrap()
+        .to_owned()
+}
+
+fn article_id(slug: &str) -> Uuid {
+    use crate::schema::articles;
+
+    articles::table
+        .filter(articles::slug.eq(slug))
+        .select(articles::id)
+        .get_result(&connection())
+        .unwrap()
+}
+
+fn delete_account(user: &TestUser, password: &str, content: &str) -> StatusCode {
+    let response = call(
+        authorized(test::TestRequest::delete(), &user.token)
+            .uri("/api/user")
+            .set_json(&json!({ "user": { "password": password, "content": content } })),
+    );
+    response.status
+}
+
+fn export(user: &TestUser) -> Vec<Value> {
+    let response = call(authorized(test::TestRequest::get(), &user.token).uri("/api/user/export"));
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text);
+
+    response
+        .text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn records<'a>(export: &'a [Value], record_type: &'a str) -> impl Iterator<Item = &'a Value> {
+    export
+        .iter()
+        .filter(move |record| record["type"] == record_type)
+        .map(|record| &record["data"])
+}
+
+// Rows in every table that still point at the user, their sessions or the article, by table
+fn remaining_rows(user_id: Uuid, footprint: &Footprint) -> Vec<(&'static str, i64)> {
+    use crate::schema::{
+        article_revisions, article_tags, articles, comments, email_verifications,
+        favorite_articles, followers, identities, login_challenges, password_reset_tokens,
+        personal_access_tokens, recovery_codes, refresh_tokens, sessions, users,
+    };
+
+    let conn = connection();
+    let article_id = footprint.article_id;
+    let session_ids = &footprint.session_ids;
+
+    let counts: Vec<(&'static str, QueryResult<i64>)> = vec![
+        (
+            "users",
+            users::table.find(user_id).count().get_result(&conn),
+        ),
+        (
+            "articles",
+            articles::table
+                .filter(
+                    articles::author_id
+                        .eq(user_id)
+                        .or(articles::id.eq(article_id)),
+                )
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "article_tags",
+            article_tags::table
+                .filter(article_tags::article_id.eq(article_id))
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "article_revisions",
+            article_revisions::table
+                .filter(article_revisions::article_id.eq(article_id))
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "comments",
+            comments::table
+                .filter(
+                    comments::user_id
+                        .eq(user_id)
+                        .or(comments::article_id.eq(article_id)),
+                )
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "favorite_articles",
+            favorite_articles::table
+                .filter(
+                    favorite_articles::user_id
+                        .eq(user_id)
+                        .or(favorite_articles::article_id.eq(article_id)),
+                )
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "followers",
+            followers::table
+                .filter(
+                    followers::user_id
+                        .eq(user_id)
+                        .or(followers::follower_id.eq(user_id)),
+                )
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "sessions",
+            sessions::table
+                .filter(
+                    sessions::user_id
+                        .eq(user_id)
+                        .or(sessions::id.eq_any(session_ids)),
+                )
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "refresh_tokens",
+            refresh_tokens::table
+                .filter(refresh_tokens::session_id.eq_any(session_ids))
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "personal_access_tokens",
+            personal_access_tokens::table
+                .filter(personal_access_tokens::user_id.eq(user_id))
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "identities",
+            identities::table
+                .filter(identities::user_id.eq(user_id))
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "recovery_codes",
+            recovery_codes::table
+                .filter(recovery_codes::user_id.eq(user_id))
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "login_challenges",
+            login_challenges::table
+                .filter(login_challenges::user_id.eq(user_id))
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "password_reset_tokens",
+            password_reset_tokens::table
+                .filter(password_reset_tokens::user_id.eq(user_id))
+                .count()
+                .get_result(&conn),
+        ),
+        (
+            "email_verifications",
+            email_verifications::table
+                .filter(email_verifications::user_id.eq(user_id))
+                .count()
+                .get_result(&conn),
+        ),
+    ];
+
+    counts
+        .into_iter()
+        .map(|(table, count)| (table, count.unwrap()))
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+#[test]
+fn deleting_an_account_removes_everything_it_left() {
+    let user = register("delete");
+    let other = register("delete");
+    let footprint = leave_footprint(&user, &other);
+
+    assert_eq!(
+        delete_account(&user, "not the password", "delete"),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    // all but the kinds of rows that only come with 2FA or password resets
+    assert_eq!(remaining_rows(user.id, &footprint).len(), 12);
+
+    assert_eq!(delete_account(&user, PASSWORD, "delete"), StatusCode::OK);
+
+    assert_eq!(remaining_rows(user.id, &footprint), vec![]);
+
+    let response = call(authorized(test::TestRequest::get(), &user.token).uri("/api/user"));
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let export = export(&other);
+    let other_comments = records(&export, "comment").collect::<Vec<_>>();
+    assert_eq!(
+        other_comments.len(),
+        0,
+        "the comment on the deleted article went with it"
+    );
+    let favorites = records(&export, "favorite")
+        .map(|favorite| favorite["articleSlug"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(favorites, Vec::<&str>::new());
+    assert_eq!(records(&export, "follower").count(), 0);
+    assert_eq!(records(&export, "following").count(), 0);
+    assert!(!export
+        .iter()
+        .any(|record| record.to_string().contains(&user.username)));
+
+    let response = call(test::TestRequest::get().uri(&format!(
+        "/api/articles/{}/comments",
+        footprint.other_article_slug
+    )));
+    assert_eq!(response.body["comments"].as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn anonymizing_an_account_keeps_its_content_under_another_name() {
+    use crate::schema::users;
+
+    let user = register("anon");
+    let other = register("anon");
+    let footprint = leave_footprint(&user, &other);
+
+    assert_eq!(delete_account(&user, PASSWORD, "anonymize"), StatusCode::OK);
+
+    // the content and what others attached to it stay, everything else about the account goes
+    let mut remaining = remaining_rows(user.id, &footprint);
+    remaining.sort();
+    assert_eq!(
+        remaining,
+        vec![
+            ("article_revisions", 1),
+            ("article_tags", 1),
+            ("articles", 1),
+            ("comments", 2),
+            ("favorite_articles", 1),
+            ("users", 1),
+        ]
+    );
+
+    let (username, email, totp_secret) = users::table
+        .find(user.id)
+        .select((users::username, users::email, users::totp_secret))
+        .get_result::<(String, String, Option<String>)>(&connection())
+        .unwrap();
+    assert!(username.starts_with("deleted_"), "{}", username);
+    assert_eq!(email, format!("{}@deleted.invalid", username));
+    assert_eq!(totp_secret, None);
+
+    let response =
+        call(test::TestRequest::get().uri(&format!("/api/articles/{}", footprint.article_slug)));
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.body["article"]["author"]["username"],
+        json!(username)
+    );
+
+    let response = call(
+        test::TestRequest::post()
+            .uri("/api/users/login")
+            .set_json(&json!({
+                "user": { "login": username, "password": PASSWORD },
+            })),
+    );
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let export = export(&other);
+    let comments = records(&export, "comment")
+        .map(|comment| comment["articleSlug"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(comments, vec![footprint.article_slug.as_str()]);
+    let favorites = records(&export, "favorite")
+        .map(|favorite| favorite["articleSlug"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(favorites, vec![footprint.article_slug.as_str()]);
+    assert_eq!(records(&export, "follower").count(), 0);
+    assert_eq!(records(&export, "following").count(), 0);
+    assert!(!export
+        .iter()
+        .any(|record| record.to_string().contains(&user.username)));
+}
+
+#[test]
+fn wrong_passwords_for_deleting_an_account_are_throttled() {
+    let user = register("throttle");
+
+    // the account's free attempts, then one that starts the lockout
+    for _ in 0..6 {
+        assert_eq!(
+            delete_account(&user, "not the password", "delete"),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+    assert_eq!(
+        delete_account(&user, "not the password", "delete"),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        delete_account(&user, PASSWORD, "delete"),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    let response = call(authorized(test::TestRequest::get(), &user.token).uri("/api/user"));
+    assert_eq!(response.status, StatusCode::OK);
+}
@@ -0,0 +1,65 @@
+use actix_web::{http::StatusCode, test};
+use diesel::prelude::*;
+
+use super::{audit_actions, authorized, call, connection, register, TestUser};
+
+fn create_article(author: &TestUser) -> String {
+    let response = call(
+        authorized(test::TestRequest::post(), &author.token)
+            .uri("/api/articles")
+            .set_json(&json!({
+                "article": {
+                    "title": format!("By {}", author.username),
+                    "description": "About something",
+                    "body": "Something",
+                    "tagList": ["comments"],
+                },
+            })),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    response.body["article"]["slug"]
+        .as_str()
+        .unwrap()
+        .to_owned()
+}
+
+fn add_comment(slug: &str, commenter: &TestUser) -> i64 {
+    let response = call(
+        authorized(test::TestRequest::post(), &commenter.token)
+            .uri(&format!("/api/articles/{}/comments", slug))
+            .set_json(&json!({ "comment": { "body": "A comment" } })),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    response.body["comment"]["id"].as_i64().unwrap()
+}
+
+fn delete_comment(slug: &str, comment_id: i64, user: &TestUser) {
+    let response = call(
+        authorized(test::TestRequest::delete(), &user.token)
+            .uri(&format!("/api/articles/{}/comments/{}", slug, comment_id)),
+    );
+    assert!(response.status.is_success(), "{}", response.text);
+}
+
+#[test]
+fn only_moderators_deleting_comments_are_audited() {
+    use crate::schema::users;
+
+    let author = register("commenter");
+    let moderator = register("moderator");
+    diesel::update(users::table.find(moderator.id))
+        .set(users::role.eq("moderator"))
+        .execute(&connection())
+        .unwrap();
+    let slug = create_article(&author);
+
+    let own = add_comment(&slug, &author);
+    delete_comment(&slug, own, &author);
+    assert!(!audit_actions(author.id).contains(&"comment.deleted".to_owned()));
+
+    let moderated = add_comment(&slug, &author);
+    delete_comment(&slug, moderated, &moderator);
+    assert!(audit_actions(moderator.id).contains(&"comment.deleted".to_owned()));
+}
@@ -0,0 +1,171 @@
+// Tests that go through the routes down to the database. They need DATABASE_URL (or a .env) to
+// point at a database with all migrations run, and create their users under random names so they
+// can share it with each other and with earlier runs.
+
+mod account_deletion;
+mod comments;
+mod oidc;
+mod password_resets;
+mod tokens;
+mod users;
+
+use actix::prelude::{Addr, SyncArbiter};
+use chrono::Utc;
+use diesel::prelude::*;
+use libreauth::oath::TOTPBuilder;
+use actix_web::{
+    http::{HeaderMap, StatusCode},
+    test,
+    web::Data,
+    App,
+};
+use serde_json::Value;
+use std::{env, sync::Once};
+use uuid::Uuid;
+
+use super::{routes, AppState};
+use crate::db::{new_pool, DbExecutor, PgPool, PooledConn};
+
+pub const PASSWORD: &str = "Tr0ub4dor&3xkcd!";
+
+static CONFIGURE: Once = Once::new();
+
+lazy_static! {
+    static ref POOL: PgPool = new_pool(database_url()).expect("Failed to create pool.");
+}
+
+thread_local! {
+    // every test runs on its own thread, with its own actix system
+    static DB: Addr<DbExecutor> = test::run_on(|| SyncArbiter::start(1, || DbExecutor(POOL.clone())));
+}
+
+fn configure() {
+    CONFIGURE.call_once(|| {
+        dotenv::dotenv().ok();
+        // tokens are signed with the development secret unless keys are configured
+        if env::var("JWT_KEYS_DIR").is_err() && env::var("JWT_SECRET").is_err() {
+            env::set_var("CONDUIT_DEV_MODE", "1");
+        }
+        oidc::serve_stub_provider();
+    });
+}
+
+pub fn database_url() -> String {
+    configure();
+    env::var("DATABASE_URL").expect("DATABASE_URL must point at a migrated database to run these tests")
+}
+
+pub fn connection() -> PooledConn {
+    POOL.get().expect("Failed to get a connection from the pool.")
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub text: String,
+    pub body: Value,
+}
+
+// Runs the request through the app's routes, bodies that aren't JSON come back as Null next to
+// their text
+pub fn call(request: test::TestRequest) -> Response {
+    let db = DB.with(Addr::clone);
+    let mut app = test::init_service(
+        App::new()
+            .register_data(Data::new(AppState { db }))
+            .configure(routes),
+    );
+
+    let response = test::call_service(&mut app, request.to_request());
+    let status = response.status();
+    let headers = response.headers().clone();
+    let text = String::from_utf8(test::read_body(response).to_vec()).unwrap();
+
+    Response {
+        status,
+        headers,
+        body: serde_json::from_str(&text).unwrap_or(Value::Null),
+        text,
+    }
+}
+
+pub fn authorized(request: test::TestRequest, token: &str) -> test::TestRequest {
+    request.header("Authorization", format!("Token {}", token))
+}
+
+// A name no other test or earlier run has used, within the 20 characters usernames can have
+pub fn unique_name(prefix: &str) -> String {
+    let suffix = Uuid::new_v4().to_simple().to_string();
+    format!("{}_{}", prefix, &suffix[..19 - prefix.len()])
+}
+
+pub struct TestUser {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub token: String,
+}
+
+pub fn register(prefix: &str) -> TestUser {
+    let username = unique_name(prefix);
+    let email = format!("{}@example.com", username);
+
+    let response = call(test::TestRequest::post().uri("/api/users").set_json(&json!({
+        "user": { "username": username, "email": email, "password": PASSWORD },
+    })));
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    TestUser {
+        id: user_id(&username),
+        token: response.body["user"]["token"].as_str().unwrap().to_owned(),
+        username,
+        email,
+    }
+}
+
+fn user_id(name: &str) -> Uuid {
+    use crate::schema::users;
+
+    users::table
+        .filter(users::username.eq(name))
+        .select(users::id)
+        .get_result(&connection())
+        .unwrap()
+}
+
+// Turns on two-factor authentication as if the user had confirmed it, returns the secret
+pub fn enable_totp(user: &TestUser) -> String {
+    use crate::schema::users;
+    use crate::utils::totp::generate_secret;
+
+    let secret = generate_secret();
+    diesel::update(users::table.find(user.id))
+        .set((
+            users::totp_secret.eq(&secret),
+            users::totp_enabled_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(&connection())
+        .unwrap();
+    secret
+}
+
+pub fn totp_code(secret: &str) -> String {
+    TOTPBuilder::new()
+        .base32_key(secret)
+        .finalize()
+        .unwrap()
+        .generate()
+}
+
+// What the user did according to the audit log, oldest first
+pub fn audit_actions(user_id: Uuid) -> Vec<String> {
+    use crate::schema::audit_events;
+
+    audit_events::table
+        .filter(audit_events::actor_id.eq(user_id))
+        .order(audit_events::id)
+        .select(audit_events::action)
+        .load(&connection())
+        .unwrap()
+}
@@ -0,0 +1,38 @@
+use actix_web::{http::StatusCode, test};
+
+use super::{call, register, unique_name, Response};
+
+fn request_reset(email: &str) -> Response {
+    call(
+        test::TestRequest::post()
+            .uri("/api/users/password-reset")
+            .set_json(&json!({ "user": { "email": email } })),
+    )
+}
+
+#[test]
+fn reset_requests_for_an_address_are_throttled() {
+    let user = register("reset");
+
+    // the address's free requests, then one that starts the lockout
+    for _ in 0..4 {
+        assert_eq!(request_reset(&user.email).status, StatusCode::OK);
+    }
+    let response = request_reset(&user.email.to_uppercase());
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers.contains_key("Retry-After"));
+    assert_eq!(
+        response.body,
+        json!({ "error": "Too many password reset requests, try again later" })
+    );
+
+    // the same goes for addresses nobody has, so the lockout gives nothing away
+    let unknown = format!("{}@example.com", unique_name("nobody"));
+    for _ in 0..4 {
+        assert_eq!(request_reset(&unknown).status, StatusCode::OK);
+    }
+    assert_eq!(request_reset(&unknown).status, StatusCode::TOO_MANY_REQUESTS);
+
+    let other = register("reset");
+    assert_eq!(request_reset(&other.email).status, StatusCode::OK);
+}
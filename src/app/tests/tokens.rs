@@ -0,0 +1,99 @@
+use actix_web::{http::StatusCode, test};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+
+use super::{authorized, call, connection, register, TestUser};
+use crate::utils::token::{generate_token, hash_token};
+
+fn create_token(user: &TestUser) -> String {
+    let response = call(
+        authorized(test::TestRequest::post(), &user.token)
+            .uri("/api/user/tokens")
+            .set_json(&json!({ "token": { "name": "ci", "scopes": ["read"] } })),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    response.body["token"]["token"].as_str().unwrap().to_owned()
+}
+
+fn works(token: &str) -> bool {
+    let response = call(authorized(test::TestRequest::get(), token).uri("/api/user"));
+    response.status == StatusCode::OK
+}
+
+// What the latest audit event about the user recorded for this action
+fn audited_changes(user: &TestUser, action: &str) -> serde_json::Value {
+    use crate::schema::audit_events;
+
+    audit_events::table
+        .filter(audit_events::target_id.eq(user.id.to_string()))
+        .filter(audit_events::action.eq(action))
+        .order(audit_events::id.desc())
+        .select(audit_events::changes)
+        .first(&connection())
+        .unwrap()
+}
+
+#[test]
+fn resetting_the_password_revokes_access_tokens() {
+    use crate::models::NewPasswordResetToken;
+    use crate::schema::password_reset_tokens;
+
+    let user = register("reset");
+    let access_token = create_token(&user);
+    assert!(works(&access_token));
+
+    // the token from the mail
+    let reset_token = generate_token();
+    diesel::insert_into(password_reset_tokens::table)
+        .values(NewPasswordResetToken {
+            user_id: user.id,
+            token_hash: hash_token(&reset_token),
+            expires_at: (Utc::now() + Duration::hours(1)).naive_utc(),
+        })
+        .execute(&connection())
+        .unwrap();
+
+    let response = call(
+        test::TestRequest::post()
+            .uri("/api/users/password-reset/confirm")
+            .set_json(&json!({
+                "user": { "token": reset_token, "password": "An0ther&l0ng-passphrase" },
+            })),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text);
+
+    assert!(!works(&access_token));
+    assert!(!works(&user.token));
+    assert_eq!(
+        audited_changes(&user, "user.password_changed"),
+        json!({ "via": "reset", "revokedTokens": 1 })
+    );
+}
+
+#[test]
+fn forcing_a_password_reset_revokes_access_tokens() {
+    use crate::schema::users;
+
+    let admin = register("admin");
+    diesel::update(users::table.find(admin.id))
+        .set(users::role.eq("admin"))
+        .execute(&connection())
+        .unwrap();
+    let user = register("forced");
+    let access_token = create_token(&user);
+    assert!(works(&access_token));
+
+    let response = call(
+        authorized(test::TestRequest::post(), &admin.token)
+            .uri(&format!("/api/admin/users/{}/password-reset", user.id)),
+    );
+    assert!(response.status.is_success(), "{}", response.text);
+
+    assert!(!works(&access_token));
+    assert!(!works(&user.token));
+    assert_eq!(
+        audited_changes(&user, "user.password_reset_forced"),
+        json!({ "revokedTokens": 1 })
+    );
+}
@@ -0,0 +1,206 @@
+use actix_web::{http::StatusCode, test};
+use diesel::{connection::SimpleConnection, pg::PgConnection, prelude::*};
+
+use super::{
+    audit_actions, authorized, call, connection, database_url, enable_totp, register, PASSWORD,
+};
+use crate::prelude::*;
+
+const CASE_INSENSITIVE_MIGRATION: &str =
+    include_str!("../../../migrations/2026-10-17-100000_case_insensitive_unique_users/up.sql");
+
+#[test]
+fn registering_a_taken_username_in_other_case_fails_on_the_username() {
+    let user = register("case");
+
+    let response = call(
+        test::TestRequest::post()
+            .uri("/api/users")
+            .set_json(&json!({
+                "user": {
+                    "username": user.username.to_uppercase(),
+                    "email": format!("other_{}", user.email),
+                    "password": PASSWORD,
+                },
+            })),
+    );
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body,
+        json!({ "errors": { "username": ["has already been taken"] } })
+    );
+}
+
+#[test]
+fn registering_a_taken_email_in_other_case_fails_on_the_email() {
+    let user = register("case");
+
+    let response = call(
+        test::TestRequest::post()
+            .uri("/api/users")
+            .set_json(&json!({
+                "user": {
+                    "username": format!("{}x", &user.username[..19]),
+                    "email": user.email.to_uppercase(),
+                    "password": PASSWORD,
+                },
+            })),
+    );
+
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body,
+        json!({ "errors": { "email": ["has already been taken"] } })
+    );
+}
+
+#[test]
+fn unique_violations_on_user_fields_map_to_validation_errors() {
+    use crate::models::NewUser;
+    use crate::schema::users;
+
+    let user = register("case");
+    let conn = connection();
+
+    let error = diesel::insert_into(users::table)
+        .values(NewUser {
+            username: user.username.to_uppercase(),
+            email: format!("other_{}", user.email),
+            password: String::new(),
+            bio: None,
+            image: None,
+        })
+        .execute(&conn)
+        .unwrap_err();
+
+    match Error::from(error) {
+        Error::UnprocessableEntity(body) => {
+            assert_eq!(
+                body,
+                json!({ "errors": { "username": ["has already been taken"] } })
+            )
+        }
+        error => panic!("expected a 422, got {:?}", error),
+    }
+}
+
+#[test]
+fn articles_are_filtered_by_author_and_favorited_in_any_case() {
+    let user = register("case");
+
+    let response = call(
+        authorized(test::TestRequest::post(), &user.token)
+            .uri("/api/articles")
+            .set_json(&json!({
+                "article": {
+                    "title": "Cases",
+                    "description": "About cases",
+                    "body": "Cases",
+                    "tagList": ["cases"],
+                },
+            })),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let slug = response.body["article"]["slug"].clone();
+
+    let response = call(
+        authorized(test::TestRequest::post(), &user.token)
+            .uri(&format!("/api/articles/{}/favorite", slug.as_str().unwrap())),
+    );
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    for filter in &["author", "favorited"] {
+        let response = call(test::TestRequest::get().uri(&format!(
+            "/api/articles?{}={}",
+            filter,
+            user.username.to_uppercase()
+        )));
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let slugs = response.body["articles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|article| article["slug"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(slugs, vec![slug.clone()], "filtered by {}", filter);
+    }
+}
+
+#[test]
+fn a_wrong_second_factor_code_leaves_the_other_changes_unsaved() {
+    let user = register("update");
+    enable_totp(&user);
+
+    let response = call(
+        authorized(test::TestRequest::put(), &user.token)
+            .uri("/api/user")
+            .set_json(&json!({
+                "user": {
+                    "username": format!("{}x", &user.username[..19]),
+                    "password": "An0ther&l0ng-passphrase",
+                    "totp": { "enabled": false, "code": "not a code" },
+                },
+            })),
+    );
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body, json!({ "errors": { "totp": ["code is invalid"] } }));
+
+    let response = call(authorized(test::TestRequest::get(), &user.token).uri("/api/user"));
+    assert_eq!(response.body["user"]["username"], json!(user.username));
+
+    let response = call(test::TestRequest::post().uri("/api/users/login").set_json(&json!({
+        "user": { "login": user.username, "password": PASSWORD },
+    })));
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text);
+    assert_eq!(response.body["challenge"]["type"], "totp");
+
+    assert!(!audit_actions(user.id).contains(&"user.password_changed".to_owned()));
+}
+
+// The migration runs against a temporary users table, which shadows the real one for the rest of
+// the transaction that is then rolled back
+fn run_migration_on(users: &[(&str, &str)]) -> QueryResult<()> {
+    let conn = PgConnection::establish(&database_url()).unwrap();
+    conn.begin_test_transaction().unwrap();
+
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE users (
+            LIKE public.users INCLUDING DEFAULTS,
+            CONSTRAINT users_username_email_key UNIQUE (username, email)
+        )",
+    )?;
+    for (username, email) in users {
+        diesel::sql_query("INSERT INTO users (username, email, password) VALUES ($1, $2, '')")
+            .bind::<diesel::sql_types::Text, _>(username)
+            .bind::<diesel::sql_types::Text, _>(email)
+            .execute(&conn)?;
+    }
+
+    conn.batch_execute(CASE_INSENSITIVE_MIGRATION)
+}
+
+#[test]
+fn migration_refuses_users_that_only_differ_in_case() {
+    let error = run_migration_on(&[
+        ("alice", "alice@example.com"),
+        ("Alice", "alice2@example.com"),
+        ("bob", "bob@example.com"),
+        ("bobby", "BOB@example.com"),
+    ])
+    .unwrap_err();
+
+    let message = error.to_string();
+    assert!(message.contains("users share a username or email that only differs in case"));
+    assert!(message.contains("username alice, Alice"), "{}", message);
+    assert!(
+        message.contains("email bob@example.com, BOB@example.com"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn migration_goes_through_without_conflicts() {
+    run_migration_on(&[("alice", "alice@example.com"), ("bob", "bob@example.com")]).unwrap();
+}
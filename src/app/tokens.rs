@@ -0,0 +1,132 @@
+use actix_web::{HttpRequest, HttpResponse, web::Json, web::Path, web::Data};
+use actix_http::error::ResponseError;
+use futures::{future::result, Future};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::AppState;
+use crate::prelude::*;
+use crate::utils::{
+    auth::{authenticate, Auth},
+    CustomDateTime,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct In<T> {
+    token: T,
+}
+
+// Extractors ↓
+
+#[derive(Debug, Deserialize)]
+pub struct TokenPath {
+    id: Uuid,
+}
+
+// Client Messages ↓
+
+#[derive(Debug)]
+pub struct GetTokens {
+    pub auth: Auth,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateToken {
+    #[validate(length(
+        min = "1",
+        max = "100",
+        message = "fails validation - must be 1-100 characters long"
+    ))]
+    pub name: String,
+    pub scopes: Vec<String>,
+    // tokens without an expiry live until they are revoked
+    #[validate(range(min = "1", max = "365", message = "fails validation - must be 1-365 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct CreateTokenOuter {
+    pub auth: Auth,
+    pub token: CreateToken,
+}
+
+#[derive(Debug)]
+pub struct RevokeToken {
+    pub auth: Auth,
+    pub id: Uuid,
+}
+
+// JSON response objects ↓
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenResponseInner {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    // the plain token is only ever shown once, right after it was created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub created_at: CustomDateTime,
+    pub last_used_at: Option<CustomDateTime>,
+    pub expires_at: Option<CustomDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub token: TokenResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenListResponse {
+    pub tokens: Vec<TokenResponseInner>,
+}
+
+// Route handlers ↓
+
+pub fn list(state: Data<AppState>, req: HttpRequest) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(GetTokens { auth }).from_err())
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn create(
+    state: Data<AppState>,
+    (form, req): (Json<In<CreateToken>>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let token = form.into_inner().token;
+
+    let db = state.db.clone();
+
+    result(token.validate())
+        .from_err()
+        .and_then(move |_| authenticate(&state, &req))
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(CreateTokenOuter { auth, token }).from_err())
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn revoke(
+    state: Data<AppState>,
+    (path, req): (Path<TokenPath>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(RevokeToken { auth, id: path.id }).from_err())
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
@@ -8,8 +8,8 @@ use super::AppState;
 use crate::models::User;
 use crate::prelude::*;
 use crate::utils::{
-    auth::{authenticate, Auth},
-    jwt::CanGenerateJwt,
+    auth::{authenticate, Auth, ClientInfo, Scope},
+    permissions::Role,
 };
 
 lazy_static! {
@@ -47,10 +47,22 @@ pub struct RegisterUser {
     pub password: String,
 }
 
+#[derive(Debug)]
+pub struct RegisterUserOuter {
+    pub client: ClientInfo,
+    pub register_user: RegisterUser,
+}
+
 #[derive(Debug, Validate, Deserialize)]
 pub struct LoginUser {
-    #[validate(email(message = "fails validation - is not a valid email address"))]
-    pub email: String,
+    // a username or an email address, clients from before usernames were accepted send `email`
+    #[serde(alias = "email")]
+    #[validate(length(
+        min = "1",
+        max = "254",
+        message = "fails validation - must be 1-254 characters long"
+    ))]
+    pub login: String,
     #[validate(length(
         min = "8",
         max = "72",
@@ -59,6 +71,12 @@ pub struct LoginUser {
     pub password: String,
 }
 
+#[derive(Debug)]
+pub struct LoginUserOuter {
+    pub client: ClientInfo,
+    pub login_user: LoginUser,
+}
+
 #[derive(Debug, Validate, Deserialize)]
 pub struct UpdateUser {
     #[validate(
@@ -85,6 +103,15 @@ pub struct UpdateUser {
     pub bio: Option<String>,
     #[validate(url(message = "is not a URL"))]
     pub image: Option<String>,
+    pub totp: Option<TotpChange>,
+}
+
+// Enabling takes two steps: without a code a new secret is generated,
+// then a code from the authenticator confirms it. Disabling needs a code as well.
+#[derive(Debug, Deserialize)]
+pub struct TotpChange {
+    pub enabled: bool,
+    pub code: Option<String>,
 }
 
 #[derive(Debug)]
@@ -93,6 +120,105 @@ pub struct UpdateUserOuter {
     pub update_user: UpdateUser,
 }
 
+#[derive(Debug, Validate, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginTotp {
+    #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
+    pub challenge_token: String,
+    // either a code from the authenticator or one of the recovery codes
+    #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
+    pub code: String,
+}
+
+#[derive(Debug)]
+pub struct LoginTotpOuter {
+    pub client: ClientInfo,
+    pub login_totp: LoginTotp,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshSession {
+    #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
+    pub refresh_token: String,
+}
+
+#[derive(Debug)]
+pub struct Logout {
+    pub auth: Auth,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct VerifyEmail {
+    #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
+    pub token: String,
+}
+
+#[derive(Debug)]
+pub struct VerifyEmailOuter {
+    pub client: ClientInfo,
+    pub verify_email: VerifyEmail,
+}
+
+#[derive(Debug)]
+pub struct ResendEmailVerification {
+    pub auth: Auth,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct RequestPasswordReset {
+    #[validate(email(message = "fails validation - is not a valid email address"))]
+    pub email: String,
+}
+
+#[derive(Debug)]
+pub struct RequestPasswordResetOuter {
+    pub client: ClientInfo,
+    pub request_password_reset: RequestPasswordReset,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct ConfirmPasswordReset {
+    #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
+    pub token: String,
+    #[validate(length(
+        min = "8",
+        max = "72",
+        message = "fails validation - must be 8-72 characters long"
+    ))]
+    pub password: String,
+}
+
+#[derive(Debug)]
+pub struct ConfirmPasswordResetOuter {
+    pub client: ClientInfo,
+    pub confirm_password_reset: ConfirmPasswordReset,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct DeleteAccount {
+    // asked for again so a stolen session alone can't delete the account
+    #[validate(length(min = "1", message = "fails validation - cannot be empty"))]
+    pub password: String,
+    // deleted unless asked otherwise
+    pub content: Option<ContentDisposal>,
+}
+
+// What happens to the articles and comments of a deleted account
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentDisposal {
+    Delete,
+    // kept, but under an anonymous name with no way to sign in
+    Anonymize,
+}
+
+#[derive(Debug)]
+pub struct DeleteAccountOuter {
+    pub auth: Auth,
+    pub delete_account: DeleteAccount,
+}
+
 // JSON response objects ↓
 
 #[derive(Debug, Serialize)]
@@ -101,52 +227,88 @@ pub struct UserResponse {
 }
 
 #[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserResponseInner {
     pub email: String,
     pub token: String,
+    // only handed out when a session is started or refreshed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
     pub username: String,
     pub bio: Option<String>,
     pub image: Option<String>,
+    pub role: Role,
+    // a new address waiting to be confirmed, the email stays the same until then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub totp: Option<TotpResponse>,
+}
+
+// Each of these is only ever shown once, right after the corresponding TotpChange
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otpauth_uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+// A correct password only gets a challenge when the user has two-factor authentication enabled
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Authenticated(UserResponse),
+    ChallengeRequired(ChallengeResponse),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChallengeResponse {
+    pub challenge: ChallengeResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeResponseInner {
+    #[serde(rename = "type")]
+    pub challenge_type: String,
+    pub token: String,
 }
 
-impl From<User> for UserResponse {
-    fn from(user: User) -> Self {
+impl UserResponse {
+    pub fn new(user: User, token: String, refresh_token: Option<String>) -> Self {
         UserResponse {
             user: UserResponseInner {
-                token: user.generate_jwt().unwrap(),
+                role: user.role(),
+                token,
+                refresh_token,
                 email: user.email,
                 username: user.username,
                 bio: user.bio,
                 image: user.image,
+                pending_email: None,
+                totp: None,
             },
         }
     }
-}
 
-impl UserResponse {
-    fn create_with_auth(auth: Auth) -> Self {
-        UserResponse {
-            user: UserResponseInner {
-                token: auth.token,
-                email: auth.user.email,
-                username: auth.user.username,
-                bio: auth.user.bio,
-                image: auth.user.image,
-            },
-        }
+    pub fn create_with_auth(auth: Auth) -> Self {
+        UserResponse::new(auth.user, auth.token, None)
     }
 }
 
 // Route handlers ↓
 
 pub fn register(
-    (form, state): (Json<In<RegisterUser>>, Data<AppState>),
+    (form, state, req): (Json<In<RegisterUser>>, Data<AppState>, HttpRequest),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     let register_user = form.into_inner().user;
+    let client = ClientInfo::from_request(&req);
 
     result(register_user.validate())
         .from_err()
-        .and_then(move |_| state.db.send(register_user).from_err())
+        .and_then(move |_| state.db.send(RegisterUserOuter { client, register_user }).from_err())
         .and_then(|res| match res {
             Ok(res) => Ok(HttpResponse::Ok().json(res)),
             Err(e) => Ok(e.error_response()),
@@ -154,24 +316,162 @@ pub fn register(
 }
 
 pub fn login(
-    (form, state): (Json<In<LoginUser>>, Data<AppState>),
+    (form, state, req): (Json<In<LoginUser>>, Data<AppState>, HttpRequest),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     let login_user = form.into_inner().user;
+    let client = ClientInfo::from_request(&req);
 
     result(login_user.validate())
         .from_err()
-        .and_then(move |_| state.db.send(login_user).from_err())
+        .and_then(move |_| state.db.send(LoginUserOuter { client, login_user }).from_err())
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn login_totp(
+    (form, state, req): (Json<In<LoginTotp>>, Data<AppState>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let login_totp = form.into_inner().user;
+    let client = ClientInfo::from_request(&req);
+
+    result(login_totp.validate())
+        .from_err()
+        .and_then(move |_| state.db.send(LoginTotpOuter { client, login_totp }).from_err())
         .and_then(|res| match res {
             Ok(res) => Ok(HttpResponse::Ok().json(res)),
             Err(e) => Ok(e.error_response()),
         })
 }
 
+pub fn refresh(
+    (form, state): (Json<In<RefreshSession>>, Data<AppState>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let refresh_session = form.into_inner().user;
+
+    result(refresh_session.validate())
+        .from_err()
+        .and_then(move |_| state.db.send(refresh_session).from_err())
+        .and_then(|res| match res {
+            Ok(res) => Ok(HttpResponse::Ok().json(res)),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn logout(state: Data<AppState>, req: HttpRequest) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(Logout { auth }).from_err())
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn verify_email(
+    (form, state, req): (Json<In<VerifyEmail>>, Data<AppState>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let verify_email = form.into_inner().user;
+    let client = ClientInfo::from_request(&req);
+
+    result(verify_email.validate())
+        .from_err()
+        .and_then(move |_| state.db.send(VerifyEmailOuter { client, verify_email }).from_err())
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn resend_email_verification(
+    state: Data<AppState>,
+    req: HttpRequest,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let db = state.db.clone();
+
+    authenticate(&state, &req)
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(ResendEmailVerification { auth }).from_err())
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn request_password_reset(
+    (form, state, req): (Json<In<RequestPasswordReset>>, Data<AppState>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let request_password_reset = form.into_inner().user;
+    let client = ClientInfo::from_request(&req);
+
+    result(request_password_reset.validate())
+        .from_err()
+        .and_then(move |_| {
+            state
+                .db
+                .send(RequestPasswordResetOuter {
+                    client,
+                    request_password_reset,
+                })
+                .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
+pub fn confirm_password_reset(
+    (form, state, req): (Json<In<ConfirmPasswordReset>>, Data<AppState>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let confirm_password_reset = form.into_inner().user;
+    let client = ClientInfo::from_request(&req);
+
+    result(confirm_password_reset.validate())
+        .from_err()
+        .and_then(move |_| {
+            state
+                .db
+                .send(ConfirmPasswordResetOuter {
+                    client,
+                    confirm_password_reset,
+                })
+                .from_err()
+        })
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
 pub fn get_current(state: Data<AppState>, req: HttpRequest) -> impl Future<Item = HttpResponse, Error = Error> {
     authenticate(&state, &req)
+        .and_then(|auth| auth.require_scope(Scope::Read))
         .and_then(|auth| Ok(HttpResponse::Ok().json(UserResponse::create_with_auth(auth))))
 }
 
+pub fn delete(
+    state: Data<AppState>,
+    (form, req): (Json<In<DeleteAccount>>, HttpRequest),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let delete_account = form.into_inner().user;
+
+    let db = state.db.clone();
+
+    result(delete_account.validate())
+        .from_err()
+        .and_then(move |_| authenticate(&state, &req))
+        .and_then(Auth::require_session)
+        .and_then(move |auth| db.send(DeleteAccountOuter { auth, delete_account }).from_err())
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+}
+
 pub fn update(
     state: Data<AppState>,
     (form, req): (Json<In<UpdateUser>>, HttpRequest),
@@ -179,10 +479,19 @@ pub fn update(
     let update_user = form.into_inner().user;
 
     let db = state.db.clone();
+    // credentials and second factors stay out of reach of personal access tokens
+    let sensitive = update_user.email.is_some() || update_user.password.is_some() || update_user.totp.is_some();
 
     result(update_user.validate())
         .from_err()
         .and_then(move |_| authenticate(&state, &req))
+        .and_then(move |auth| {
+            if sensitive {
+                auth.require_session()
+            } else {
+                auth.require_scope(Scope::UserWrite)
+            }
+        })
         .and_then(move |auth| db.send(UpdateUserOuter { auth, update_user }).from_err())
         .and_then(|res| match res {
             Ok(res) => Ok(HttpResponse::Ok().json(res)),
@@ -0,0 +1,43 @@
+use diesel::prelude::*;
+use std::{collections::BTreeMap, env, process};
+
+use crate::db::new_pool;
+use crate::prelude::*;
+use crate::utils::{DUMMY_PASSWORD_HASH, HASHER};
+
+// Maintenance commands, run as `conduit <command>` instead of starting the server
+
+pub fn run(command: fn() -> Result<()>) {
+    if let Err(e) = command() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+// How many users still have a password hash made with other settings than the current ones.
+// Those are only replaced when their user logs in next.
+pub fn hash_report() -> Result<()> {
+    use crate::schema::users;
+
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let conn = new_pool(database_url)?.get()?;
+
+    let hashes = users::table.select(users::password).load::<String>(&conn)?;
+
+    let mut outdated: BTreeMap<String, usize> = BTreeMap::new();
+    for hash in hashes.iter().filter(|hash| HASHER.needs_rehash(hash)) {
+        *outdated.entry(HASHER.describe(hash)).or_default() += 1;
+    }
+
+    println!("current: {}", HASHER.describe(&DUMMY_PASSWORD_HASH));
+    println!(
+        "{} of {} users have a password hash made with old settings",
+        outdated.values().sum::<usize>(),
+        hashes.len()
+    );
+    for (settings, count) in &outdated {
+        println!("{:>8}  {}", count, settings);
+    }
+
+    Ok(())
+}
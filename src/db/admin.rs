@@ -0,0 +1,336 @@
+use actix::prelude::*;
+use chrono::Utc;
+use diesel::{pg::Pg, prelude::*};
+use uuid::Uuid;
+
+use super::{
+    audit::{change, record_audit_event, AuditTarget},
+    password_resets::send_password_reset,
+    sessions::revoke_all_sessions,
+    tokens::revoke_all_tokens,
+    users::delete_user,
+    DbExecutor,
+};
+use crate::app::admin::{
+    AdminUserListResponse, AdminUserResponse, AdminUserResponseInner, ChangeRoleOuter, DeleteUser,
+    ForcePasswordReset, GetUsers, SuspendUser, UnsuspendUser, UsersParams,
+};
+use crate::models::User;
+use crate::prelude::*;
+use crate::schema::users;
+use crate::utils::{
+    permissions::{require_permission, Permission, Role},
+    token::generate_token,
+    CustomDateTime, HASHER,
+};
+
+// message handler implementations ↓
+
+impl Message for GetUsers {
+    type Result = Result<AdminUserListResponse>;
+}
+
+impl Handler<GetUsers> for DbExecutor {
+    type Result = Result<AdminUserListResponse>;
+
+    fn handle(&mut self, msg: GetUsers, _: &mut Self::Context) -> Self::Result {
+        require_permission(&msg.auth.user, Permission::ManageUsers)?;
+
+        if let Some(ref role) = msg.params.role {
+            if Role::parse(role).is_none() {
+                return Err(Error::UnprocessableEntity(json!({
+                    "errors": { "role": ["must be one of user, moderator, admin"] },
+                })));
+            }
+        }
+
+        let conn = &self.0.get()?;
+
+        let limit = std::cmp::min(msg.params.limit.unwrap_or(20), 100) as i64;
+        let offset = msg.params.offset.unwrap_or(0) as i64;
+
+        let users_count = filter_users(&msg.params).count().get_result::<i64>(conn)?;
+        let matched_users = filter_users(&msg.params)
+            .order(users::created_at.desc())
+            .limit(limit)
+            .offset(offset)
+            .load::<User>(conn)?;
+
+        Ok(AdminUserListResponse {
+            users: matched_users.into_iter().map(AdminUserResponseInner::from).collect(),
+            users_count,
+        })
+    }
+}
+
+impl Message for SuspendUser {
+    type Result = Result<AdminUserResponse>;
+}
+
+impl Handler<SuspendUser> for DbExecutor {
+    type Result = Result<AdminUserResponse>;
+
+    fn handle(&mut self, msg: SuspendUser, _: &mut Self::Context) -> Self::Result {
+        require_permission(&msg.auth.user, Permission::ManageUsers)?;
+        forbid_self(&msg.auth.user, msg.id)?;
+
+        let conn = &self.0.get()?;
+
+        let user = conn.transaction::<_, Error, _>(|| {
+            let user = users::table.find(msg.id).get_result::<User>(conn)?;
+
+            // suspending twice keeps the original date
+            let user = match user.suspended_at {
+                Some(_) => user,
+                None => {
+                    let user = diesel::update(&user)
+                        .set(users::suspended_at.eq(Utc::now().naive_utc()))
+                        .get_result::<User>(conn)?;
+
+                    record_audit_event(
+                        conn,
+                        Some(msg.auth.user.id),
+                        &msg.auth.client,
+                        "user.suspended",
+                        Some(AuditTarget::User(user.id)),
+                        json!({ "suspended": change(false, true) }),
+                    )?;
+                    user
+                }
+            };
+
+            revoke_all_sessions(user.id, conn)?;
+            Ok(user)
+        })?;
+
+        log::info!("{} suspended user {}", msg.auth.user.username, user.username);
+
+        Ok(AdminUserResponse { user: user.into() })
+    }
+}
+
+impl Message for UnsuspendUser {
+    type Result = Result<AdminUserResponse>;
+}
+
+impl Handler<UnsuspendUser> for DbExecutor {
+    type Result = Result<AdminUserResponse>;
+
+    fn handle(&mut self, msg: UnsuspendUser, _: &mut Self::Context) -> Self::Result {
+        require_permission(&msg.auth.user, Permission::ManageUsers)?;
+
+        let conn = &self.0.get()?;
+
+        let user = conn.transaction::<_, Error, _>(|| {
+            let user = users::table.find(msg.id).get_result::<User>(conn)?;
+
+            if user.suspended_at.is_none() {
+                return Ok(user);
+            }
+
+            let user = diesel::update(&user)
+                .set(users::suspended_at.eq(None::<chrono::NaiveDateTime>))
+                .get_result::<User>(conn)?;
+
+            record_audit_event(
+                conn,
+                Some(msg.auth.user.id),
+                &msg.auth.client,
+                "user.unsuspended",
+                Some(AuditTarget::User(user.id)),
+                json!({ "suspended": change(true, false) }),
+            )?;
+            Ok(user)
+        })?;
+
+        log::info!("{} unsuspended user {}", msg.auth.user.username, user.username);
+
+        Ok(AdminUserResponse { user: user.into() })
+    }
+}
+
+impl Message for ForcePasswordReset {
+    type Result = Result<()>;
+}
+
+impl Handler<ForcePasswordReset> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: ForcePasswordReset, _: &mut Self::Context) -> Self::Result {
+        require_permission(&msg.auth.user, Permission::ManageUsers)?;
+
+        let conn = &self.0.get()?;
+
+        // a random password nobody knows, so the only way back in is the reset mail
+        let unusable_password = HASHER.hash(&generate_token())?;
+
+        let user = conn.transaction::<_, Error, _>(|| {
+            let user = diesel::update(users::table.find(msg.id))
+                .set(users::password.eq(unusable_password))
+                .get_result::<User>(conn)?;
+
+            revoke_all_sessions(user.id, conn)?;
+            let revoked_tokens = revoke_all_tokens(user.id, conn)?;
+
+            record_audit_event(
+                conn,
+                Some(msg.auth.user.id),
+                &msg.auth.client,
+                "user.password_reset_forced",
+                Some(AuditTarget::User(user.id)),
+                json!({ "revokedTokens": revoked_tokens }),
+            )?;
+            Ok(user)
+        })?;
+
+        send_password_reset(&user, conn)?;
+
+        log::info!("{} forced a password reset for user {}", msg.auth.user.username, user.username);
+
+        Ok(())
+    }
+}
+
+impl Message for ChangeRoleOuter {
+    type Result = Result<AdminUserResponse>;
+}
+
+impl Handler<ChangeRoleOuter> for DbExecutor {
+    type Result = Result<AdminUserResponse>;
+
+    fn handle(&mut self, msg: ChangeRoleOuter, _: &mut Self::Context) -> Self::Result {
+        require_permission(&msg.auth.user, Permission::ManageUsers)?;
+        forbid_self(&msg.auth.user, msg.id)?;
+
+        let role = Role::parse(&msg.change_role.role).ok_or_else(|| {
+            Error::UnprocessableEntity(json!({
+                "errors": { "role": ["must be one of user, moderator, admin"] },
+            }))
+        })?;
+
+        let conn = &self.0.get()?;
+
+        let user = conn.transaction::<_, Error, _>(|| {
+            let user = users::table.find(msg.id).get_result::<User>(conn)?;
+            let previous_role = user.role();
+
+            let user = diesel::update(&user)
+                .set(users::role.eq(role.as_str()))
+                .get_result::<User>(conn)?;
+
+            if previous_role != role {
+                record_audit_event(
+                    conn,
+                    Some(msg.auth.user.id),
+                    &msg.auth.client,
+                    "user.role_changed",
+                    Some(AuditTarget::User(user.id)),
+                    json!({ "role": change(previous_role.as_str(), role.as_str()) }),
+                )?;
+            }
+            Ok(user)
+        })?;
+
+        log::info!(
+            "{} changed the role of user {} to {}",
+            msg.auth.user.username,
+            user.username,
+            role.as_str()
+        );
+
+        Ok(AdminUserResponse { user: user.into() })
+    }
+}
+
+impl Message for DeleteUser {
+    type Result = Result<()>;
+}
+
+impl Handler<DeleteUser> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: DeleteUser, _: &mut Self::Context) -> Self::Result {
+        require_permission(&msg.auth.user, Permission::ManageUsers)?;
+        forbid_self(&msg.auth.user, msg.id)?;
+
+        let conn = &self.0.get()?;
+
+        let user = users::table.find(msg.id).get_result::<User>(conn)?;
+
+        conn.transaction::<_, Error, _>(|| {
+            delete_user(user.id, conn)?;
+
+            record_audit_event(
+                conn,
+                Some(msg.auth.user.id),
+                &msg.auth.client,
+                "user.deleted",
+                Some(AuditTarget::User(user.id)),
+                json!({
+                    "username": change(Some(&user.username), None),
+                    "content": "deleted",
+                }),
+            )
+        })?;
+
+        log::info!("{} deleted user {}", msg.auth.user.username, user.username);
+
+        Ok(())
+    }
+}
+
+impl From<User> for AdminUserResponseInner {
+    fn from(user: User) -> Self {
+        AdminUserResponseInner {
+            role: user.role(),
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+            totp_enabled: user.totp_enabled_at.is_some(),
+            suspended_at: user.suspended_at.map(CustomDateTime),
+            created_at: CustomDateTime(user.created_at),
+        }
+    }
+}
+
+// local helper methods ↓
+
+fn filter_users(params: &UsersParams) -> users::BoxedQuery<'static, Pg> {
+    let mut query = users::table.into_boxed();
+
+    if let Some(ref q) = params.q {
+        // the search term is matched literally, not as a LIKE pattern
+        let pattern = format!(
+            "%{}%",
+            q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        );
+        query = query.filter(
+            users::username
+                .ilike(pattern.to_owned())
+                .or(users::email.ilike(pattern)),
+        );
+    }
+
+    if let Some(ref role) = params.role {
+        query = query.filter(users::role.eq(role.to_owned()));
+    }
+
+    match params.suspended {
+        Some(true) => query = query.filter(users::suspended_at.is_not_null()),
+        Some(false) => query = query.filter(users::suspended_at.is_null()),
+        None => {}
+    }
+
+    query
+}
+
+// An admin locking themselves out, or removing the last admin, is best done by hand
+fn forbid_self(admin: &User, user_id: Uuid) -> Result<()> {
+    if admin.id == user_id {
+        return Err(Error::Forbidden(json!({
+            "error": "admins can't do this to their own account",
+        })));
+    }
+    Ok(())
+}
@@ -1,20 +1,35 @@
 use actix::prelude::*;
 use blob_uuid::to_blob;
-use diesel::prelude::*;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{pg::Pg, prelude::*, sql_types::Nullable, sql_types::Timestamp};
 use slug::slugify;
 use uuid::Uuid;
 
-use super::{DbExecutor, PooledConn};
+use super::{
+    audit::{change, record_audit_event, AuditTarget},
+    lower,
+    revisions::save_revision,
+    DbExecutor, PooledConn,
+};
+use crate::app::scheduler::PublishDueArticles;
 use crate::app::articles::{
     ArticleListResponse, ArticleResponse, ArticleResponseInner, CreateArticleOuter, DeleteArticle,
-    FavoriteArticle, GetArticle, GetArticles, GetFeed, UnfavoriteArticle, UpdateArticleOuter,
+    FavoriteArticle, GetArticle, GetArticles, GetDrafts, GetFeed, PublishArticle,
+    UnfavoriteArticle, UpdateArticleOuter,
 };
 use crate::app::profiles::ProfileResponseInner;
 use crate::models::{
-    Article, ArticleChange, ArticleTag, NewArticle, NewArticleTag, NewFavoriteArticle, User,
+    Article, ArticleChange, ArticleStatus, ArticleTag, NewArticle, NewArticleTag,
+    NewFavoriteArticle, User,
 };
 use crate::prelude::*;
-use crate::utils::CustomDateTime;
+use crate::utils::{
+    etag::version_matches,
+    markdown::render_markdown,
+    pagination::{Cursor, Page, PageLinks},
+    permissions::{authorize, Permission, Role},
+    CustomDateTime,
+};
 
 // message handler implementations ↓
 
@@ -28,6 +43,17 @@ impl Handler<CreateArticleOuter> for DbExecutor {
     fn handle(&mut self, msg: CreateArticleOuter, _: &mut Self::Context) -> Self::Result {
         use crate::schema::articles;
 
+        let publish_at = parse_publish_at(&msg.article.publish_at)?;
+        let status = match (&msg.article.status, publish_at) {
+            (Some(status), _) => parse_status(status)?,
+            // scheduled articles wait as drafts
+            (None, Some(_)) => ArticleStatus::Draft,
+            (None, None) => ArticleStatus::Published,
+        };
+        if publish_at.is_some() {
+            forbid_scheduling_published(status)?;
+        }
+
         let conn = &self.0.get()?;
 
         let author = msg.auth.user;
@@ -37,6 +63,7 @@ impl Handler<CreateArticleOuter> for DbExecutor {
         let new_article_id = Uuid::new_v4();
         let slug = generate_slug(&new_article_id, &msg.article.title);
 
+        let body_html = render_markdown(&msg.article.body);
         let new_article = NewArticle {
             id: new_article_id,
             author_id: author.id,
@@ -44,6 +71,13 @@ impl Handler<CreateArticleOuter> for DbExecutor {
             title: msg.article.title,
             description: msg.article.description,
             body: msg.article.body,
+            status: status.as_str().to_owned(),
+            published_at: match status {
+                ArticleStatus::Published => Some(Utc::now().naive_utc()),
+                _ => None,
+            },
+            publish_at,
+            body_html,
         };
         let article = diesel::insert_into(articles::table)
             .values(&new_article)
@@ -65,10 +99,10 @@ impl Handler<GetArticle> for DbExecutor {
     fn handle(&mut self, msg: GetArticle, _: &mut Self::Context) -> Self::Result {
         let conn = &self.0.get()?;
 
-        match msg.auth {
-            Some(auth) => get_article_response(msg.slug, Some(auth.user.id), conn),
-            None => get_article_response(msg.slug, None, conn),
-        }
+        let viewer_id = msg.auth.map(|auth| auth.user.id);
+        let article = find_visible_article(&msg.slug, viewer_id, conn)?;
+
+        get_article_response(article.slug, viewer_id, conn)
     }
 }
 
@@ -82,16 +116,25 @@ impl Handler<UpdateArticleOuter> for DbExecutor {
     fn handle(&mut self, msg: UpdateArticleOuter, _: &mut Self::Context) -> Self::Result {
         use crate::schema::articles;
 
+        let status = match msg.article.status {
+            Some(ref status) => Some(parse_status(status)?),
+            None => None,
+        };
+        let publish_at = parse_publish_at(&msg.article.publish_at)?;
+
         let conn = &self.0.get()?;
 
-        let article = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .get_result::<Article>(conn)?;
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
+
+        let acting_role = authorize(
+            &msg.auth.user,
+            article.author_id,
+            Permission::EditArticle,
+            &article.slug,
+        )?;
 
-        if msg.auth.user.id != article.author_id {
-            return Err(Error::Forbidden(json!({
-                "error": "user is not the author of article in question",
-            })));
+        if publish_at.is_some() {
+            forbid_scheduling_published(status.unwrap_or_else(|| article.status()))?;
         }
 
         let slug = match &msg.article.title {
@@ -103,58 +146,224 @@ impl Handler<UpdateArticleOuter> for DbExecutor {
             slug,
             title: msg.article.title,
             description: msg.article.description,
+            body_html: msg.article.body.as_ref().map(|body| render_markdown(body)),
             body: msg.article.body,
+            status: status.map(|status| status.as_str().to_owned()),
+            published_at: match status {
+                Some(ArticleStatus::Published) if article.published_at.is_none() => {
+                    Some(Utc::now().naive_utc())
+                }
+                _ => None,
+            },
+            publish_at: match (publish_at, status) {
+                (Some(publish_at), _) => Some(Some(publish_at)),
+                (None, Some(_)) => Some(None),
+                (None, None) => None,
+            },
         };
 
-        let article = diesel::update(articles::table.find(article.id))
-            .set(&article_change)
-            .get_result::<Article>(conn)?;
+        let changes: serde_json::Map<String, serde_json::Value> = [
+            ("title", &article.title, &article_change.title),
+            ("description", &article.description, &article_change.description),
+            ("body", &article.body, &article_change.body),
+        ]
+        .iter()
+        .filter_map(|(field, from, to)| match to {
+            Some(to) if to != *from => Some((field.to_string(), change(*from, to))),
+            _ => None,
+        })
+        .collect();
+
+        let tag_list = msg.article.tag_list;
+        let if_match = msg.if_match;
+        let auth = &msg.auth;
+
+        let article = conn.transaction::<_, Error, _>(|| {
+            // checked against the locked row, so a concurrent edit can't slip in between
+            if let Some(ref if_match) = if_match {
+                let current_version = articles::table
+                    .find(article.id)
+                    .select(articles::version)
+                    .for_update()
+                    .get_result::<i32>(conn)?;
+
+                if !version_matches(if_match, current_version) {
+                    return Err(Error::PreconditionFailed(json!({
+                        "error": "article was changed since it was read, fetch it again",
+                    })));
+                }
+            }
 
-        let _ = match msg.article.tag_list {
-            Some(tags) => {
-                let inserted_tags = replace_tags(article.id, tags, conn)?;
-                inserted_tags
-                    .iter()
-                    .map(|article_tag| article_tag.tag_name.to_owned())
-                    .collect::<Vec<String>>()
+            // a revision is kept for every change readers can see, tags and status don't count
+            if !changes.is_empty() {
+                save_revision(article.id, conn)?;
             }
-            None => select_tags_on_article(article.id, conn)?,
-        };
 
-        get_article_response(article.slug, Some(article.author_id), conn)
+            // authors editing their own articles isn't worth an audit event, moderators doing it
+            // is, including what changed that readers don't see in a revision
+            if acting_role.is_some() {
+                let mut details = changes;
+                if let Some(ref status) = article_change.status {
+                    if *status != article.status {
+                        details.insert("status".into(), change(&article.status, status));
+                    }
+                }
+                if let Some(publish_at) = article_change.publish_at {
+                    if publish_at != article.publish_at {
+                        let publish_at = change(
+                            article.publish_at.map(CustomDateTime),
+                            publish_at.map(CustomDateTime),
+                        );
+                        details.insert("publishAt".into(), publish_at);
+                    }
+                }
+                if let Some(ref tags) = tag_list {
+                    let mut current_tags = select_tags_on_article(article.id, conn)?;
+                    current_tags.sort();
+                    let mut new_tags = tags.clone();
+                    new_tags.sort();
+                    new_tags.dedup();
+                    if new_tags != current_tags {
+                        details.insert("tagList".into(), change(current_tags, new_tags));
+                    }
+                }
+
+                record_audit_event(
+                    conn,
+                    Some(auth.user.id),
+                    &auth.client,
+                    "article.updated",
+                    Some(AuditTarget::Article(article.id)),
+                    details.into(),
+                )?;
+            }
+
+            // also when only the tags change, they live in their own table but the article's
+            // updatedAt and version have to move with them
+            let article = diesel::update(articles::table.find(article.id))
+                .set((&article_change, articles::updated_at.eq(Utc::now().naive_utc())))
+                .get_result::<Article>(conn)?;
+
+            if let Some(tags) = tag_list {
+                replace_tags(article.id, tags, conn)?;
+            }
+
+            Ok(article)
+        })?;
+
+        let mut response = get_article_response(article.slug, Some(msg.auth.user.id), conn)?;
+        response.acting_role = acting_role;
+        Ok(response)
     }
 }
 
 impl Message for DeleteArticle {
-    type Result = Result<()>;
+    type Result = Result<Option<Role>>;
 }
 
 impl Handler<DeleteArticle> for DbExecutor {
-    type Result = Result<()>;
+    type Result = Result<Option<Role>>;
 
     fn handle(&mut self, msg: DeleteArticle, _: &mut Self::Context) -> Self::Result {
         use crate::schema::articles;
 
         let conn = &self.0.get()?;
 
-        let article = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .get_result::<Article>(conn)?;
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
 
-        if msg.auth.user.id != article.author_id {
-            return Err(Error::Forbidden(json!({
-                "error": "user is not the author of article in question",
-            })));
-        }
+        let acting_role = authorize(
+            &msg.auth.user,
+            article.author_id,
+            Permission::DeleteArticle,
+            &article.slug,
+        )?;
+
+        conn.transaction::<_, Error, _>(|| {
+            delete_tags(article.id, conn)?;
+
+            delete_favorites(article.id, conn)?;
+
+            delete_comments(article.id, conn)?;
+
+            delete_revisions(article.id, conn)?;
+
+            diesel::delete(articles::table.filter(articles::id.eq(article.id))).execute(conn)?;
+
+            record_audit_event(
+                conn,
+                Some(msg.auth.user.id),
+                &msg.auth.client,
+                "article.deleted",
+                Some(AuditTarget::Article(article.id)),
+                json!({
+                    "slug": change(Some(&article.slug), None),
+                    "title": change(Some(&article.title), None),
+                    "authorId": article.author_id,
+                }),
+            )?;
+
+            Ok(acting_role)
+        })
+    }
+}
+
+impl Message for PublishArticle {
+    type Result = Result<ArticleResponse>;
+}
 
-        delete_tags(article.id, conn)?;
+impl Handler<PublishArticle> for DbExecutor {
+    type Result = Result<ArticleResponse>;
 
-        delete_favorites(article.id, conn)?;
+    fn handle(&mut self, msg: PublishArticle, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::articles;
 
-        match diesel::delete(articles::table.filter(articles::id.eq(article.id))).execute(conn) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.into()),
+        let conn = &self.0.get()?;
+
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
+
+        let acting_role = authorize(
+            &msg.auth.user,
+            article.author_id,
+            Permission::EditArticle,
+            &article.slug,
+        )?;
+
+        // publishing again is a no-op, and an article that was published before keeps its date
+        if article.status() != ArticleStatus::Published {
+            conn.transaction::<_, Error, _>(|| {
+                diesel::update(&article)
+                    .set((
+                        articles::status.eq(ArticleStatus::Published.as_str()),
+                        articles::published_at
+                            .eq(article.published_at.unwrap_or_else(|| Utc::now().naive_utc())),
+                        articles::publish_at.eq(None::<NaiveDateTime>),
+                    ))
+                    .execute(conn)?;
+
+                if acting_role.is_some() {
+                    record_audit_event(
+                        conn,
+                        Some(msg.auth.user.id),
+                        &msg.auth.client,
+                        "article.published",
+                        Some(AuditTarget::Article(article.id)),
+                        json!({
+                            "status": change(
+                                article.status.as_str(),
+                                ArticleStatus::Published.as_str(),
+                            ),
+                            "authorId": article.author_id,
+                        }),
+                    )?;
+                }
+
+                Ok(())
+            })?;
         }
+
+        let mut response = get_article_response(article.slug, Some(msg.auth.user.id), conn)?;
+        response.acting_role = acting_role;
+        Ok(response)
     }
 }
 
@@ -166,13 +375,11 @@ impl Handler<FavoriteArticle> for DbExecutor {
     type Result = Result<ArticleResponse>;
 
     fn handle(&mut self, msg: FavoriteArticle, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, favorite_articles};
+        use crate::schema::favorite_articles;
 
         let conn = &self.0.get()?;
 
-        let article = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .get_result::<Article>(conn)?;
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
 
         diesel::insert_into(favorite_articles::table)
             .values(NewFavoriteArticle {
@@ -193,13 +400,11 @@ impl Handler<UnfavoriteArticle> for DbExecutor {
     type Result = Result<ArticleResponse>;
 
     fn handle(&mut self, msg: UnfavoriteArticle, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, favorite_articles};
+        use crate::schema::favorite_articles;
 
         let conn = &self.0.get()?;
 
-        let article = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .get_result::<Article>(conn)?;
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
 
         diesel::delete(favorite_articles::table)
             .filter(favorite_articles::user_id.eq(msg.auth.user.id))
@@ -222,54 +427,77 @@ impl Handler<GetArticles> for DbExecutor {
 
         let conn = &self.0.get()?;
 
-        let mut query = articles::table.into_boxed();
-
-        if let Some(ref author_name) = msg.params.author {
-            let articles_by_author = articles::table
-                .inner_join(users::table)
-                .filter(users::username.eq(author_name))
-                .select(articles::id)
-                .load::<Uuid>(conn)?;
-
-            query = query.filter(articles::id.eq_any(articles_by_author));
-        }
-
-        if let Some(ref username_favorited_by) = msg.params.favorited {
-            use crate::schema::favorite_articles;
-
-            let favorite_article_ids: Vec<Uuid> = favorite_articles::table
-                .inner_join(users::table)
-                .filter(users::username.eq(username_favorited_by))
-                .select(favorite_articles::article_id)
-                .load::<Uuid>(conn)?;
+        let articles_by_author = match msg.params.author {
+            Some(ref author_name) => Some(
+                articles::table
+                    .inner_join(users::table)
+                    .filter(lower(users::username).eq(author_name.to_lowercase()))
+                    .select(articles::id)
+                    .load::<Uuid>(conn)?,
+            ),
+            None => None,
+        };
 
-            query = query.filter(articles::id.eq_any(favorite_article_ids));
-        }
+        let favorite_article_ids = match msg.params.favorited {
+            Some(ref username_favorited_by) => {
+                use crate::schema::favorite_articles;
+
+                Some(
+                    favorite_articles::table
+                        .inner_join(users::table)
+                        .filter(lower(users::username).eq(username_favorited_by.to_lowercase()))
+                        .select(favorite_articles::article_id)
+                        .load::<Uuid>(conn)?,
+                )
+            }
+            None => None,
+        };
 
-        if let Some(ref tag) = msg.params.tag {
-            use crate::schema::article_tags;
+        let tagged_article_ids = match msg.params.tag {
+            Some(ref tag) => {
+                use crate::schema::article_tags;
 
-            let tagged_article_ids: Vec<Uuid> = article_tags::table
-                .filter(article_tags::tag_name.eq(tag))
-                .select(article_tags::article_id)
-                .load::<Uuid>(conn)?;
+                Some(
+                    article_tags::table
+                        .filter(article_tags::tag_name.eq(tag))
+                        .select(article_tags::article_id)
+                        .load::<Uuid>(conn)?,
+                )
+            }
+            None => None,
+        };
 
-            query = query.filter(articles::id.eq_any(tagged_article_ids));
-        }
+        // once to count every match and once for the page
+        let matching = || {
+            // articles by suspended users are hidden along with them
+            let active_authors = users::table
+                .filter(users::suspended_at.is_null())
+                .select(users::id);
+            let mut query = articles::table
+                .filter(articles::status.eq(ArticleStatus::Published.as_str()))
+                .filter(articles::author_id.eq_any(active_authors))
+                .into_boxed();
+
+            if let Some(ref ids) = articles_by_author {
+                query = query.filter(articles::id.eq_any(ids));
+            }
+            if let Some(ref ids) = favorite_article_ids {
+                query = query.filter(articles::id.eq_any(ids));
+            }
+            if let Some(ref ids) = tagged_article_ids {
+                query = query.filter(articles::id.eq_any(ids));
+            }
+            query
+        };
 
         let limit = std::cmp::min(msg.params.limit.unwrap_or(20), 100) as i64;
-        let offset = msg.params.offset.unwrap_or(0) as i64;
+        let page = Page::parse(msg.params.offset, &msg.params.after, &msg.params.before)?;
 
-        let matched_articles = query
-            .order(articles::created_at.desc())
-            .limit(limit)
-            .offset(offset)
-            .load::<Article>(conn)?;
+        let articles_count = matching().count().get_result::<i64>(conn)?;
+        let (matched_articles, links) = load_published_page(matching(), &page, limit, conn)?;
 
-        match msg.auth {
-            Some(auth) => get_article_list_response(matched_articles, Some(auth.user.id), conn),
-            None => get_article_list_response(matched_articles, None, conn),
-        }
+        let user_id = msg.auth.map(|auth| auth.user.id);
+        get_article_list_response(matched_articles, articles_count, links, user_id, conn)
     }
 }
 
@@ -281,39 +509,159 @@ impl Handler<GetFeed> for DbExecutor {
     type Result = Result<ArticleListResponse>;
 
     fn handle(&mut self, msg: GetFeed, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, followers};
+        use crate::schema::{articles, followers, users};
 
         let conn = &self.0.get()?;
 
         let limit = std::cmp::min(msg.params.limit.unwrap_or(20), 100) as i64;
-        let offset = msg.params.offset.unwrap_or(0) as i64;
+        let page = Page::parse(msg.params.offset, &msg.params.after, &msg.params.before)?;
 
         let user_id = msg.auth.user.id;
 
         let following_ids = followers::table
+            .inner_join(users::table.on(users::id.eq(followers::user_id)))
             .filter(followers::follower_id.eq(user_id))
+            .filter(users::suspended_at.is_null())
             .select(followers::user_id)
             .load::<Uuid>(conn)?;
 
-        let articles = articles::table
-            .filter(articles::author_id.eq_any(following_ids))
-            .order(articles::created_at.desc())
-            .limit(limit)
-            .offset(offset)
-            .get_results::<Article>(conn)?;
+        let followed = || {
+            articles::table
+                .filter(articles::status.eq(ArticleStatus::Published.as_str()))
+                .filter(articles::author_id.eq_any(&following_ids))
+                .into_boxed()
+        };
 
-        get_article_list_response(articles, Some(user_id), conn)
+        let articles_count = followed().count().get_result::<i64>(conn)?;
+        let (articles, links) = load_published_page(followed(), &page, limit, conn)?;
+
+        get_article_list_response(articles, articles_count, links, Some(user_id), conn)
     }
 }
 
-// local helper methods ↓
+impl Message for GetDrafts {
+    type Result = Result<ArticleListResponse>;
+}
+
+impl Handler<GetDrafts> for DbExecutor {
+    type Result = Result<ArticleListResponse>;
+
+    fn handle(&mut self, msg: GetDrafts, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::articles;
+
+        let conn = &self.0.get()?;
+
+        let limit = std::cmp::min(msg.params.limit.unwrap_or(20), 100) as i64;
+        let page = Page::parse(msg.params.offset, &msg.params.after, &msg.params.before)?;
+
+        let drafts = || {
+            articles::table
+                .filter(articles::author_id.eq(msg.auth.user.id))
+                .filter(articles::status.eq(ArticleStatus::Draft.as_str()))
+                .into_boxed()
+        };
+
+        let drafts_count = drafts().count().get_result::<i64>(conn)?;
+
+        // the most recently edited first, the one extra draft tells whether there's another page
+        let query = drafts().limit(limit + 1);
+        let query = match page {
+            Page::Offset(offset) => query
+                .order((articles::updated_at.desc(), articles::id.desc()))
+                .offset(offset),
+            Page::After(ref cursor) => query
+                .filter(
+                    articles::updated_at.lt(cursor.time).or(articles::updated_at
+                        .eq(cursor.time)
+                        .and(articles::id.lt(cursor.id))),
+                )
+                .order((articles::updated_at.desc(), articles::id.desc())),
+            Page::Before(ref cursor) => query
+                .filter(
+                    articles::updated_at.gt(cursor.time).or(articles::updated_at
+                        .eq(cursor.time)
+                        .and(articles::id.gt(cursor.id))),
+                )
+                .order((articles::updated_at.asc(), articles::id.asc())),
+        };
+        let (drafts, links) = page.finish(query.load::<Article>(conn)?, limit, |draft| Cursor {
+            time: draft.updated_at,
+            id: draft.id,
+        });
+
+        get_article_list_response(drafts, drafts_count, links, Some(msg.auth.user.id), conn)
+    }
+}
+
+impl Message for PublishDueArticles {
+    type Result = Result<Vec<String>>;
+}
+
+impl Handler<PublishDueArticles> for DbExecutor {
+    type Result = Result<Vec<String>>;
+
+    fn handle(&mut self, _msg: PublishDueArticles, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::articles;
+
+        let conn = &self.0.get()?;
+
+        let now = Utc::now().naive_utc();
+
+        conn.transaction::<_, Error, _>(|| {
+            // rows locked by another server's scheduler are skipped rather than waited for,
+            // so every article is published exactly once
+            let due_article_ids = articles::table
+                .filter(articles::publish_at.le(now))
+                .select(articles::id)
+                .limit(100)
+                .for_update()
+                .skip_locked()
+                .load::<Uuid>(conn)?;
+
+            // dated when it was scheduled for rather than when the scheduler got to it, unless it
+            // was published before
+            let due_articles = articles::table.filter(articles::id.eq_any(due_article_ids));
+            let published_slugs = diesel::update(due_articles)
+                .set((
+                    articles::status.eq(ArticleStatus::Published.as_str()),
+                    articles::published_at.eq(coalesce(articles::published_at, articles::publish_at)),
+                    articles::publish_at.eq(None::<NaiveDateTime>),
+                ))
+                .returning(articles::slug)
+                .get_results::<String>(conn)?;
+
+            Ok(published_slugs)
+        })
+    }
+}
+
+// helper methods ↓
+
+// Looks an article up the way the viewer may see it: drafts are not found for anyone but their author
+pub fn find_visible_article(
+    slug: &str,
+    viewer_id: Option<Uuid>,
+    conn: &PooledConn,
+) -> Result<Article> {
+    use crate::schema::articles;
 
-fn generate_slug(uuid: &Uuid, title: &str) -> String {
+    let article = articles::table
+        .filter(articles::slug.eq(slug))
+        .get_result::<Article>(conn)?;
+
+    if article.status() == ArticleStatus::Draft && Some(article.author_id) != viewer_id {
+        return Err(diesel::result::Error::NotFound.into());
+    }
+
+    Ok(article)
+}
+
+pub fn generate_slug(uuid: &Uuid, title: &str) -> String {
     format!("{}-{}", to_blob(uuid), slugify(title))
 }
 
 // This will reduce the amount of boilerplate when an ArticleResponse is needed
-fn get_article_response(
+pub fn get_article_response(
     slug: String,
     user_id: Option<Uuid>,
     conn: &PooledConn,
@@ -334,15 +682,28 @@ fn get_article_response(
 
     let tags = select_tags_on_article(article.id, conn)?;
 
+    let body_html = match article.body_html {
+        Some(ref body_html) => body_html.to_owned(),
+        // rendering again is cheaper than saving it, which would count as an edit
+        None => render_markdown(&article.body),
+    };
+
     Ok(ArticleResponse {
+        acting_role: None,
+        version: article.version,
         article: ArticleResponseInner {
+            status: article.status(),
             slug: article.slug,
             title: article.title,
             description: article.description,
             body: article.body,
+            body_html: Some(body_html),
+            snippet: None,
             tag_list: tags,
             created_at: CustomDateTime(article.created_at),
             updated_at: CustomDateTime(article.updated_at),
+            published_at: article.published_at.map(CustomDateTime),
+            publish_at: article.publish_at.map(CustomDateTime),
             favorited,
             favorites_count,
             author: ProfileResponseInner {
@@ -355,8 +716,85 @@ fn get_article_response(
     })
 }
 
-fn get_article_list_response(
+// local helper methods ↓
+
+// Published articles, newest first. The one extra article loaded tells whether there's another page.
+fn load_published_page(
+    query: crate::schema::articles::BoxedQuery<'_, Pg>,
+    page: &Page<Uuid>,
+    limit: i64,
+    conn: &PooledConn,
+) -> Result<(Vec<Article>, PageLinks)> {
+    use crate::schema::articles;
+
+    let query = query.limit(limit + 1);
+    let query = match page {
+        Page::Offset(offset) => query
+            .order((articles::published_at.desc(), articles::id.desc()))
+            .offset(*offset),
+        Page::After(cursor) => query
+            .filter(
+                articles::published_at.lt(cursor.time).or(articles::published_at
+                    .eq(cursor.time)
+                    .and(articles::id.lt(cursor.id))),
+            )
+            .order((articles::published_at.desc(), articles::id.desc())),
+        Page::Before(cursor) => query
+            .filter(
+                articles::published_at.gt(cursor.time).or(articles::published_at
+                    .eq(cursor.time)
+                    .and(articles::id.gt(cursor.id))),
+            )
+            .order((articles::published_at.asc(), articles::id.asc())),
+    };
+
+    Ok(page.finish(query.load::<Article>(conn)?, limit, |article| Cursor {
+        // published articles always have it
+        time: article.published_at.unwrap_or(article.created_at),
+        id: article.id,
+    }))
+}
+
+sql_function!(fn coalesce(x: Nullable<Timestamp>, y: Nullable<Timestamp>) -> Nullable<Timestamp>);
+
+fn parse_publish_at(publish_at: &Option<String>) -> Result<Option<NaiveDateTime>> {
+    let publish_at = match publish_at {
+        Some(publish_at) => publish_at,
+        None => return Ok(None),
+    };
+
+    match DateTime::parse_from_rfc3339(publish_at) {
+        Ok(time) if time.naive_utc() > Utc::now().naive_utc() => Ok(Some(time.naive_utc())),
+        Ok(_) => Err(Error::UnprocessableEntity(json!({
+            "errors": { "publishAt": ["must be in the future"] },
+        }))),
+        Err(_) => Err(Error::UnprocessableEntity(json!({
+            "errors": { "publishAt": ["must be a date and time like 2019-06-01T12:00:00Z"] },
+        }))),
+    }
+}
+
+fn forbid_scheduling_published(status: ArticleStatus) -> Result<()> {
+    match status {
+        ArticleStatus::Published => Err(Error::UnprocessableEntity(json!({
+            "errors": { "publishAt": ["can only be set on drafts and unlisted articles"] },
+        }))),
+        _ => Ok(()),
+    }
+}
+
+fn parse_status(status: &str) -> Result<ArticleStatus> {
+    ArticleStatus::parse(status).ok_or_else(|| {
+        Error::UnprocessableEntity(json!({
+            "errors": { "status": ["must be one of draft, published, unlisted"] },
+        }))
+    })
+}
+
+pub fn get_article_list_response(
     articles: Vec<Article>,
+    articles_count: i64,
+    links: PageLinks,
     user_id: Option<Uuid>,
     conn: &PooledConn,
 ) -> Result<ArticleListResponse> {
@@ -371,8 +809,9 @@ fn get_article_list_response(
         .collect::<Result<Vec<ArticleResponseInner>>>()?;
 
     Ok(ArticleListResponse {
-        articles_count: article_list.len(),
         articles: article_list,
+        articles_count: articles_count as usize,
+        links,
     })
 }
 
@@ -409,6 +848,21 @@ fn delete_favorites(article_id: Uuid, conn: &PooledConn) -> Result<()> {
     Ok(())
 }
 
+fn delete_comments(article_id: Uuid, conn: &PooledConn) -> Result<()> {
+    use crate::schema::comments;
+
+    diesel::delete(comments::table.filter(comments::article_id.eq(article_id))).execute(conn)?;
+    Ok(())
+}
+
+fn delete_revisions(article_id: Uuid, conn: &PooledConn) -> Result<()> {
+    use crate::schema::article_revisions;
+
+    diesel::delete(article_revisions::table.filter(article_revisions::article_id.eq(article_id)))
+        .execute(conn)?;
+    Ok(())
+}
+
 fn replace_tags<I>(article_id: Uuid, tags: I, conn: &PooledConn) -> Result<Vec<ArticleTag>>
 where
     I: IntoIterator<Item = String>,
@@ -0,0 +1,179 @@
+use actix::prelude::*;
+use chrono::{DateTime, NaiveDateTime};
+use diesel::{pg::Pg, prelude::*};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use super::{DbExecutor, PooledConn};
+use crate::app::admin::{
+    AuditActorResponse, AuditEventListResponse, AuditEventResponseInner, AuditParams,
+    AuditTargetResponse, GetAuditEvents,
+};
+use crate::models::{AuditEvent, NewAuditEvent};
+use crate::prelude::*;
+use crate::schema::{audit_events, users};
+use crate::utils::{
+    auth::ClientInfo,
+    permissions::{require_permission, Permission},
+    CustomDateTime,
+};
+
+// What an audit event is about
+#[derive(Debug)]
+pub enum AuditTarget {
+    User(Uuid),
+    Article(Uuid),
+    Comment(i32),
+}
+
+impl AuditTarget {
+    fn into_parts(self) -> (String, String) {
+        match self {
+            AuditTarget::User(id) => ("user".into(), id.to_string()),
+            AuditTarget::Article(id) => ("article".into(), id.to_string()),
+            AuditTarget::Comment(id) => ("comment".into(), id.to_string()),
+        }
+    }
+}
+
+// message handler implementations ↓
+
+impl Message for GetAuditEvents {
+    type Result = Result<AuditEventListResponse>;
+}
+
+impl Handler<GetAuditEvents> for DbExecutor {
+    type Result = Result<AuditEventListResponse>;
+
+    fn handle(&mut self, msg: GetAuditEvents, _: &mut Self::Context) -> Self::Result {
+        require_permission(&msg.auth.user, Permission::ManageUsers)?;
+
+        let since = parse_time("since", &msg.params.since)?;
+        let until = parse_time("until", &msg.params.until)?;
+
+        let conn = &self.0.get()?;
+
+        let limit = std::cmp::min(msg.params.limit.unwrap_or(20), 100) as i64;
+        let offset = msg.params.offset.unwrap_or(0) as i64;
+
+        let audit_events_count = filter_audit_events(&msg.params, since, until)
+            .count()
+            .get_result::<i64>(conn)?;
+        let matched_events = filter_audit_events(&msg.params, since, until)
+            .order(audit_events::id.desc())
+            .limit(limit)
+            .offset(offset)
+            .load::<AuditEvent>(conn)?;
+
+        // actors that were deleted since are shown by id only
+        let actor_ids: Vec<Uuid> = matched_events.iter().filter_map(|event| event.actor_id).collect();
+        let actor_names = users::table
+            .filter(users::id.eq_any(actor_ids))
+            .select((users::id, users::username))
+            .load::<(Uuid, String)>(conn)?;
+
+        let audit_events = matched_events
+            .into_iter()
+            .map(|event| AuditEventResponseInner {
+                actor: event.actor_id.map(|actor_id| AuditActorResponse {
+                    id: actor_id,
+                    username: actor_names
+                        .iter()
+                        .find(|(id, _)| *id == actor_id)
+                        .map(|(_, username)| username.to_owned()),
+                }),
+                target: match (event.target_type, event.target_id) {
+                    (Some(target_type), Some(id)) => Some(AuditTargetResponse { target_type, id }),
+                    _ => None,
+                },
+                id: event.id,
+                action: event.action,
+                ip_address: event.ip_address,
+                user_agent: event.user_agent,
+                changes: event.changes,
+                created_at: CustomDateTime(event.created_at),
+            })
+            .collect();
+
+        Ok(AuditEventListResponse {
+            audit_events,
+            audit_events_count,
+        })
+    }
+}
+
+// helper methods ↓
+
+// Appends an event to the audit log. Pass the connection that made the change, and call this
+// inside its transaction where there is one, so the change isn't stored without its event.
+pub fn record_audit_event(
+    conn: &PooledConn,
+    actor_id: Option<Uuid>,
+    client: &ClientInfo,
+    action: &str,
+    target: Option<AuditTarget>,
+    changes: JsonValue,
+) -> Result<()> {
+    let (target_type, target_id) = match target.map(AuditTarget::into_parts) {
+        Some((target_type, target_id)) => (Some(target_type), Some(target_id)),
+        None => (None, None),
+    };
+
+    diesel::insert_into(audit_events::table)
+        .values(NewAuditEvent {
+            actor_id,
+            action: action.to_owned(),
+            target_type,
+            target_id,
+            ip_address: client.ip_address.to_owned(),
+            user_agent: client.user_agent.to_owned(),
+            changes,
+        })
+        .execute(conn)?;
+
+    Ok(())
+}
+
+// One field of an event's changes, e.g. "role": {"from": "user", "to": "admin"}
+pub fn change<T: Serialize>(from: T, to: T) -> JsonValue {
+    json!({ "from": from, "to": to })
+}
+
+// local helper methods ↓
+
+fn filter_audit_events(
+    params: &AuditParams,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+) -> audit_events::BoxedQuery<'static, Pg> {
+    let mut query = audit_events::table.into_boxed();
+
+    if let Some(actor_id) = params.actor {
+        query = query.filter(audit_events::actor_id.eq(actor_id));
+    }
+    if let Some(ref action) = params.action {
+        query = query.filter(audit_events::action.eq(action.to_owned()));
+    }
+    if let Some(since) = since {
+        query = query.filter(audit_events::created_at.ge(since));
+    }
+    if let Some(until) = until {
+        query = query.filter(audit_events::created_at.lt(until));
+    }
+
+    query
+}
+
+fn parse_time(field: &str, value: &Option<String>) -> Result<Option<NaiveDateTime>> {
+    match value {
+        Some(value) => DateTime::parse_from_rfc3339(value)
+            .map(|time| Some(time.naive_utc()))
+            .map_err(|_| {
+                Error::UnprocessableEntity(json!({
+                    "errors": { field: ["must be a date and time like 2019-06-01T12:00:00Z"] },
+                }))
+            }),
+        None => Ok(None),
+    }
+}
@@ -1,11 +1,14 @@
 use actix::prelude::*;
+use chrono::{Duration, Utc};
 use diesel::prelude::*;
 
-use crate::db::DbExecutor;
+use crate::db::{DbExecutor, PooledConn};
+use crate::models::{PersonalAccessToken, Session, User};
 use crate::prelude::*;
 use crate::utils::{
-    auth::{Auth, GenerateAuth},
+    auth::{Auth, ClientInfo, Credentials, GenerateAuth, Scope},
     jwt::CanDecodeJwt,
+    token::hash_token,
 };
 
 // message handler implementations ↓
@@ -18,18 +21,122 @@ impl Handler<GenerateAuth> for DbExecutor {
     type Result = Result<Auth>;
 
     fn handle(&mut self, msg: GenerateAuth, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::users::dsl::*;
+        match msg.credentials {
+            Credentials::Jwt(token) => {
+                let conn = &self.0.get()?;
+                authenticate_session(token, msg.client, conn)
+            }
+            Credentials::PersonalAccessToken(token) => {
+                let conn = &self.0.get()?;
+                authenticate_personal_access_token(token, msg.client, conn)
+            }
+        }
+    }
+}
 
-        let claims = msg.token.decode_jwt()?.claims;
+// helper methods ↓
 
-        let conn = &self.0.get()?;
+fn authenticate_session(token: String, client: ClientInfo, conn: &PooledConn) -> Result<Auth> {
+    use crate::schema::{sessions, users};
 
-        match users.find(claims.id).first(conn) {
-            Ok(user) => Ok(Auth {
-                user,
-                token: msg.token,
-            }),
-            Err(e) => Err(e.into()),
-        }
+    let claims = token.decode_jwt()?.claims;
+
+    let (session, user) = sessions::table
+        .inner_join(users::table)
+        .filter(sessions::id.eq(claims.sid))
+        .filter(users::id.eq(claims.id))
+        .get_result::<(Session, User)>(conn)
+        .optional()?
+        .ok_or_else(|| Error::Unauthorized(json!({
+            "error": "Session was not found",
+        })))?;
+
+    let now = Utc::now().naive_utc();
+
+    if session.revoked_at.is_some() || session.expires_at < now {
+        return Err(Error::Unauthorized(json!({
+            "error": "Session has been revoked",
+        })));
+    }
+
+    check_not_suspended(&user)?;
+
+    // no need to write to the session on every single request
+    if session.last_seen_at + Duration::minutes(1) < now {
+        diesel::update(sessions::table.find(session.id))
+            .set((
+                sessions::last_seen_at.eq(now),
+                sessions::ip_address.eq(client.ip_address.clone().or(session.ip_address)),
+            ))
+            .execute(conn)?;
+    }
+
+    Ok(Auth {
+        user,
+        token,
+        session_id: Some(session.id),
+        scopes: None,
+        client,
+    })
+}
+
+fn authenticate_personal_access_token(
+    token: String,
+    client: ClientInfo,
+    conn: &PooledConn,
+) -> Result<Auth> {
+    use crate::schema::{personal_access_tokens, users};
+
+    let now = Utc::now().naive_utc();
+
+    let (personal_access_token, user) = personal_access_tokens::table
+        .inner_join(users::table)
+        .filter(personal_access_tokens::token_hash.eq(hash_token(&token)))
+        .filter(personal_access_tokens::revoked_at.is_null())
+        .filter(
+            personal_access_tokens::expires_at
+                .is_null()
+                .or(personal_access_tokens::expires_at.gt(now)),
+        )
+        .get_result::<(PersonalAccessToken, User)>(conn)
+        .optional()?
+        .ok_or_else(|| Error::Unauthorized(json!({
+            "error": "Token is invalid",
+        })))?;
+
+    check_not_suspended(&user)?;
+
+    let last_used_long_ago = match personal_access_token.last_used_at {
+        Some(last_used_at) => last_used_at + Duration::minutes(1) < now,
+        None => true,
+    };
+    if last_used_long_ago {
+        diesel::update(personal_access_tokens::table.find(personal_access_token.id))
+            .set(personal_access_tokens::last_used_at.eq(now))
+            .execute(conn)?;
+    }
+
+    // scopes were checked when the token was created, anything unknown is simply ignored
+    let scopes = personal_access_token
+        .scopes
+        .iter()
+        .filter_map(|scope| Scope::parse(scope))
+        .collect();
+
+    Ok(Auth {
+        user,
+        token,
+        session_id: None,
+        scopes: Some(scopes),
+        client,
+    })
+}
+
+pub fn check_not_suspended(user: &User) -> Result<()> {
+    if user.suspended_at.is_some() {
+        return Err(Error::Forbidden(json!({
+            "error": "This account has been suspended",
+        })));
     }
+    Ok(())
 }
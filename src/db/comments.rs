@@ -2,7 +2,11 @@ use actix::prelude::*;
 use diesel::prelude::*;
 use uuid::Uuid;
 
-use super::{DbExecutor, PooledConn};
+use super::{
+    articles::find_visible_article,
+    audit::{change, record_audit_event, AuditTarget},
+    DbExecutor, PooledConn,
+};
 use crate::app::articles::comments::{
     AddCommentOuter, CommentListResponse, CommentResponse, CommentResponseInner, DeleteComment,
     GetComments,
@@ -10,7 +14,12 @@ use crate::app::articles::comments::{
 use crate::app::profiles::ProfileResponseInner;
 use crate::models::{Comment, Follower, NewComment, User};
 use crate::prelude::*;
-use crate::utils::CustomDateTime;
+use crate::utils::{
+    markdown::render_markdown,
+    pagination::{Cursor, Page, PageLinks},
+    permissions::{authorize, Permission, Role},
+    CustomDateTime,
+};
 
 // message handler implementations ↓
 
@@ -22,20 +31,18 @@ impl Handler<AddCommentOuter> for DbExecutor {
     type Result = Result<CommentResponse>;
 
     fn handle(&mut self, msg: AddCommentOuter, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, comments};
+        use crate::schema::comments;
 
         let conn = &self.0.get()?;
 
-        let article_id = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .select(articles::id)
-            .get_result::<Uuid>(conn)?;
-
         let user_id = msg.auth.user.id;
 
+        let article_id = find_visible_article(&msg.slug, Some(user_id), conn)?.id;
+
         let new_comment = NewComment {
             article_id,
             user_id,
+            body_html: render_markdown(&msg.comment.body),
             body: msg.comment.body,
         };
 
@@ -55,32 +62,65 @@ impl Handler<GetComments> for DbExecutor {
     type Result = Result<CommentListResponse>;
 
     fn handle(&mut self, msg: GetComments, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, comments};
+        use crate::schema::comments;
 
         let conn = &self.0.get()?;
 
-        let article_id = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .select(articles::id)
-            .get_result::<Uuid>(conn)?;
+        let viewer_id = msg.auth.as_ref().map(|auth| auth.user.id);
+        let article_id = find_visible_article(&msg.slug, viewer_id, conn)?.id;
+
+        let limit = std::cmp::min(msg.params.limit.unwrap_or(100), 100) as i64;
+        let page = Page::parse(msg.params.offset, &msg.params.after, &msg.params.before)?;
+
+        let comments_count = comments::table
+            .filter(comments::article_id.eq(article_id))
+            .count()
+            .get_result::<i64>(conn)?;
 
-        let comments = comments::table
+        // oldest first, the one extra comment tells whether there's another page
+        let query = comments::table
             .filter(comments::article_id.eq(article_id))
-            .load::<Comment>(conn)?;
+            .limit(limit + 1)
+            .into_boxed();
+        let query = match page {
+            Page::Offset(offset) => query
+                .order((comments::created_at.asc(), comments::id.asc()))
+                .offset(offset),
+            Page::After(ref cursor) => query
+                .filter(
+                    comments::created_at.gt(cursor.time).or(comments::created_at
+                        .eq(cursor.time)
+                        .and(comments::id.gt(cursor.id))),
+                )
+                .order((comments::created_at.asc(), comments::id.asc())),
+            Page::Before(ref cursor) => query
+                .filter(
+                    comments::created_at.lt(cursor.time).or(comments::created_at
+                        .eq(cursor.time)
+                        .and(comments::id.lt(cursor.id))),
+                )
+                .order((comments::created_at.desc(), comments::id.desc())),
+        };
+        let (comments, links) = page.finish(query.load::<Comment>(conn)?, limit, |comment| Cursor {
+            time: comment.created_at,
+            id: comment.id,
+        });
 
         match msg.auth {
-            Some(auth) => get_comment_list_response(comments, Some(auth.user.id), conn),
-            None => get_comment_list_response(comments, None, conn),
+            Some(auth) => {
+                get_comment_list_response(comments, comments_count, links, Some(auth.user.id), conn)
+            }
+            None => get_comment_list_response(comments, comments_count, links, None, conn),
         }
     }
 }
 
 impl Message for DeleteComment {
-    type Result = Result<()>;
+    type Result = Result<Option<Role>>;
 }
 
 impl Handler<DeleteComment> for DbExecutor {
-    type Result = Result<()>;
+    type Result = Result<Option<Role>>;
 
     fn handle(&mut self, msg: DeleteComment, _: &mut Self::Context) -> Self::Result {
         use crate::schema::comments::dsl::*;
@@ -91,15 +131,36 @@ impl Handler<DeleteComment> for DbExecutor {
             .filter(id.eq(msg.comment_id))
             .get_result::<Comment>(conn)?;
 
-        if msg.auth.user.id != comment.user_id {
-            return Err(Error::Forbidden(json!({
-                "error": "user did not make this comment",
-            })));
-        }
-
-        diesel::delete(comments.filter(id.eq(comment.id))).execute(conn)?;
-
-        Ok(())
+        let acting_role = authorize(
+            &msg.auth.user,
+            comment.user_id,
+            Permission::DeleteComment,
+            &comment.id.to_string(),
+        )?;
+
+        conn.transaction::<_, Error, _>(|| {
+            diesel::delete(comments.filter(id.eq(comment.id))).execute(conn)?;
+
+            // authors deleting their own comments isn't audited, moderators doing it is
+            if acting_role.is_some() {
+                record_audit_event(
+                    conn,
+                    Some(msg.auth.user.id),
+                    &msg.auth.client,
+                    "comment.deleted",
+                    Some(AuditTarget::Comment(comment.id)),
+                    json!({
+                        "body": change(Some(&comment.body), None),
+                        "articleId": comment.article_id,
+                        "authorId": comment.user_id,
+                    }),
+                )?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(acting_role)
     }
 }
 
@@ -130,6 +191,10 @@ fn get_comment_response(
             id: comment.id,
             created_at: CustomDateTime(comment.created_at),
             updated_at: CustomDateTime(comment.updated_at),
+            body_html: Some(match comment.body_html {
+                Some(body_html) => body_html,
+                None => render_markdown(&comment.body),
+            }),
             body: comment.body,
             author: ProfileResponseInner {
                 username: commenter.username,
@@ -143,6 +208,8 @@ fn get_comment_response(
 
 fn get_comment_list_response(
     comments: Vec<Comment>,
+    comments_count: i64,
+    links: PageLinks,
     user_id: Option<Uuid>,
     conn: &PooledConn,
 ) -> Result<CommentListResponse> {
@@ -158,5 +225,7 @@ fn get_comment_list_response(
 
     Ok(CommentListResponse {
         comments: comment_list,
+        comments_count: comments_count as usize,
+        links,
     })
 }
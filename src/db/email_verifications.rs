@@ -0,0 +1,157 @@
+use actix::prelude::*;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+
+use super::{
+    audit::{change, record_audit_event, AuditTarget},
+    lower, DbExecutor, PooledConn,
+};
+use crate::app::users::{ResendEmailVerification, VerifyEmailOuter};
+use crate::models::{EmailVerification, NewEmailVerification, User};
+use crate::prelude::*;
+use crate::utils::{
+    mailer::{Mail, MAILER},
+    token::{generate_token, hash_token},
+};
+
+pub const EMAIL_VERIFICATION_LIFETIME_HOURS: i64 = 24;
+
+// message handler implementations ↓
+
+impl Message for VerifyEmailOuter {
+    type Result = Result<()>;
+}
+
+impl Handler<VerifyEmailOuter> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: VerifyEmailOuter, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::{email_verifications, users};
+
+        let conn = &self.0.get()?;
+
+        conn.transaction::<_, Error, _>(|| {
+            let now = Utc::now().naive_utc();
+
+            let verification = email_verifications::table
+                .filter(email_verifications::token_hash.eq(hash_token(&msg.verify_email.token)))
+                .filter(email_verifications::used_at.is_null())
+                .filter(email_verifications::expires_at.gt(now))
+                .for_update()
+                .get_result::<EmailVerification>(conn)
+                .optional()?
+                .ok_or_else(|| {
+                    Error::UnprocessableEntity(json!({
+                        "errors": { "token": ["is invalid or has expired"] },
+                    }))
+                })?;
+
+            let previous_email = users::table
+                .find(verification.user_id)
+                .select(users::email)
+                .get_result::<String>(conn)?;
+
+            // someone else may have signed up with the address while it waited for confirmation
+            let taken = users::table
+                .filter(lower(users::email).eq(verification.email.to_lowercase()))
+                .filter(users::id.ne(verification.user_id))
+                .first::<User>(conn)
+                .optional()?
+                .is_some();
+            if taken {
+                return Err(Error::UnprocessableEntity(json!({
+                    "errors": { "email": ["has already been taken"] },
+                })));
+            }
+
+            // for a pending change this is where the new address actually takes over
+            diesel::update(users::table.find(verification.user_id))
+                .set((
+                    users::email.eq(&verification.email),
+                    users::email_verified_at.eq(now),
+                ))
+                .execute(conn)?;
+
+            if previous_email != verification.email {
+                record_audit_event(
+                    conn,
+                    Some(verification.user_id),
+                    &msg.client,
+                    "user.email_changed",
+                    Some(AuditTarget::User(verification.user_id)),
+                    json!({ "email": change(&previous_email, &verification.email) }),
+                )?;
+            }
+
+            // any other outstanding verification is for an address that's no longer wanted
+            diesel::update(email_verifications::table)
+                .filter(email_verifications::user_id.eq(verification.user_id))
+                .filter(email_verifications::used_at.is_null())
+                .set(email_verifications::used_at.eq(now))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+}
+
+impl Message for ResendEmailVerification {
+    type Result = Result<()>;
+}
+
+impl Handler<ResendEmailVerification> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: ResendEmailVerification, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        let user = msg.auth.user;
+
+        if user.email_verified_at.is_some() {
+            return Err(Error::UnprocessableEntity(json!({
+                "errors": { "email": ["is already verified"] },
+            })));
+        }
+
+        let mail = create_email_verification(&user, &user.email, conn)?;
+        MAILER.send(&mail)
+    }
+}
+
+// helper methods ↓
+
+// Stores a token that, once confirmed, makes `email` the verified email of `user`, and returns
+// the mail that carries it. Send it once the changes that go with it are committed.
+pub fn create_email_verification(user: &User, email: &str, conn: &PooledConn) -> Result<Mail> {
+    use crate::schema::email_verifications;
+
+    let token = generate_token();
+
+    diesel::insert_into(email_verifications::table)
+        .values(NewEmailVerification {
+            user_id: user.id,
+            email: email.to_owned(),
+            token_hash: hash_token(&token),
+            expires_at: (Utc::now() + Duration::hours(EMAIL_VERIFICATION_LIFETIME_HOURS)).naive_utc(),
+        })
+        .execute(conn)?;
+
+    Ok(Mail {
+        to: email.to_owned(),
+        subject: "Confirm your email address".into(),
+        body: format!(
+            "Please confirm that this address belongs to your account {}.\r\n\r\n\
+             Use this token within {} hours to confirm it:\r\n\r\n{}\r\n\r\n\
+             If this wasn't you, you can ignore this mail.",
+            user.username, EMAIL_VERIFICATION_LIFETIME_HOURS, token
+        ),
+    })
+}
+
+// For a verification mail that goes with changes that were already saved: failing to send it
+// doesn't undo them, the user can ask for another one
+pub fn send_email_verification(mail: &Mail) {
+    if let Err(e) = MAILER.send(mail) {
+        log::warn!("failed to send the email verification to {}: {}", mail.to, e);
+    }
+}
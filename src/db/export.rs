@@ -0,0 +1,215 @@
+use actix::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::DbExecutor;
+use crate::app::export::{
+    ExportArticle, ExportArticleRevision, ExportComment, ExportData, ExportFavorite, ExportFollow, ExportIdentity,
+    ExportPersonalAccessToken, ExportRecord, ExportSession, ExportUser,
+};
+use crate::models::{Article, ArticleRevision, Comment, Follower, Identity, PersonalAccessToken, Session, User};
+use crate::prelude::*;
+use crate::utils::CustomDateTime;
+
+// message handler implementations ↓
+
+impl Message for ExportData {
+    type Result = Result<Vec<ExportRecord>>;
+}
+
+impl Handler<ExportData> for DbExecutor {
+    type Result = Result<Vec<ExportRecord>>;
+
+    fn handle(&mut self, msg: ExportData, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::{
+            article_revisions, article_tags, articles, comments, favorite_articles, followers, identities,
+            personal_access_tokens, sessions, users,
+        };
+
+        let conn = &self.0.get()?;
+        let user_id = msg.auth.user.id;
+
+        let mut records = vec![ExportRecord::User(msg.auth.user.into())];
+
+        let own_sessions = sessions::table
+            .filter(sessions::user_id.eq(user_id))
+            .order(sessions::created_at.asc())
+            .load::<Session>(conn)?;
+        records.extend(own_sessions.into_iter().map(|session| ExportRecord::Session(session.into())));
+
+        let own_tokens = personal_access_tokens::table
+            .filter(personal_access_tokens::user_id.eq(user_id))
+            .order(personal_access_tokens::created_at.asc())
+            .load::<PersonalAccessToken>(conn)?;
+        records.extend(
+            own_tokens
+                .into_iter()
+                .map(|token| ExportRecord::PersonalAccessToken(token.into())),
+        );
+
+        let own_identities = identities::table
+            .filter(identities::user_id.eq(user_id))
+            .order(identities::created_at.asc())
+            .load::<Identity>(conn)?;
+        records.extend(own_identities.into_iter().map(|identity| {
+            ExportRecord::Identity(ExportIdentity {
+                provider: identity.provider,
+                subject: identity.subject,
+                email: identity.email,
+                created_at: CustomDateTime(identity.created_at),
+            })
+        }));
+
+        let own_articles = articles::table
+            .filter(articles::author_id.eq(user_id))
+            .order(articles::created_at.asc())
+            .load::<Article>(conn)?;
+        let mut tags_by_article: HashMap<Uuid, Vec<String>> = HashMap::new();
+        for (article_id, tag_name) in article_tags::table
+            .filter(article_tags::article_id.eq_any(own_articles.iter().map(|article| article.id)))
+            .select((article_tags::article_id, article_tags::tag_name))
+            .load::<(Uuid, String)>(conn)?
+        {
+            tags_by_article.entry(article_id).or_default().push(tag_name);
+        }
+        records.extend(own_articles.into_iter().map(|article| {
+            ExportRecord::Article(ExportArticle {
+                tag_list: tags_by_article.remove(&article.id).unwrap_or_default(),
+                id: article.id,
+                slug: article.slug,
+                title: article.title,
+                description: article.description,
+                body: article.body,
+                status: article.status,
+                created_at: CustomDateTime(article.created_at),
+                updated_at: CustomDateTime(article.updated_at),
+                published_at: article.published_at.map(CustomDateTime),
+                publish_at: article.publish_at.map(CustomDateTime),
+            })
+        }));
+
+        let own_revisions = article_revisions::table
+            .inner_join(articles::table)
+            .filter(articles::author_id.eq(user_id))
+            .order((article_revisions::article_id, article_revisions::number))
+            .select(article_revisions::all_columns)
+            .load::<ArticleRevision>(conn)?;
+        records.extend(own_revisions.into_iter().map(|revision| {
+            ExportRecord::ArticleRevision(ExportArticleRevision {
+                article_id: revision.article_id,
+                number: revision.number,
+                title: revision.title,
+                description: revision.description,
+                body: revision.body,
+                created_at: CustomDateTime(revision.created_at),
+            })
+        }));
+
+        let own_comments = comments::table
+            .inner_join(articles::table)
+            .filter(comments::user_id.eq(user_id))
+            .order(comments::created_at.asc())
+            .select((comments::all_columns, articles::slug))
+            .load::<(Comment, String)>(conn)?;
+        records.extend(own_comments.into_iter().map(|(comment, article_slug)| {
+            ExportRecord::Comment(ExportComment {
+                id: comment.id,
+                article_slug,
+                body: comment.body,
+                created_at: CustomDateTime(comment.created_at),
+                updated_at: CustomDateTime(comment.updated_at),
+            })
+        }));
+
+        let favorites = favorite_articles::table
+            .inner_join(articles::table)
+            .filter(favorite_articles::user_id.eq(user_id))
+            .order(favorite_articles::created_at.asc())
+            .select((articles::slug, favorite_articles::created_at))
+            .load::<(String, NaiveDateTime)>(conn)?;
+        records.extend(favorites.into_iter().map(|(article_slug, created_at)| {
+            ExportRecord::Favorite(ExportFavorite {
+                article_slug,
+                created_at: CustomDateTime(created_at),
+            })
+        }));
+
+        // the people following this user
+        let followed_by = followers::table
+            .inner_join(users::table.on(users::id.eq(followers::follower_id)))
+            .filter(followers::user_id.eq(user_id))
+            .order(followers::created_at.asc())
+            .select((followers::all_columns, users::username))
+            .load::<(Follower, String)>(conn)?;
+        records.extend(followed_by.into_iter().map(|(follower, username)| {
+            ExportRecord::Follower(ExportFollow {
+                username,
+                created_at: CustomDateTime(follower.created_at),
+            })
+        }));
+
+        // and the people this user follows
+        let following = followers::table
+            .inner_join(users::table.on(users::id.eq(followers::user_id)))
+            .filter(followers::follower_id.eq(user_id))
+            .order(followers::created_at.asc())
+            .select((followers::all_columns, users::username))
+            .load::<(Follower, String)>(conn)?;
+        records.extend(following.into_iter().map(|(follower, username)| {
+            ExportRecord::Following(ExportFollow {
+                username,
+                created_at: CustomDateTime(follower.created_at),
+            })
+        }));
+
+        Ok(records)
+    }
+}
+
+// the password hash and TOTP secret are deliberately left out
+impl From<User> for ExportUser {
+    fn from(user: User) -> Self {
+        ExportUser {
+            role: user.role(),
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            bio: user.bio,
+            image: user.image,
+            email_verified_at: user.email_verified_at.map(CustomDateTime),
+            totp_enabled_at: user.totp_enabled_at.map(CustomDateTime),
+            created_at: CustomDateTime(user.created_at),
+            updated_at: CustomDateTime(user.updated_at),
+        }
+    }
+}
+
+impl From<Session> for ExportSession {
+    fn from(session: Session) -> Self {
+        ExportSession {
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: CustomDateTime(session.created_at),
+            last_seen_at: CustomDateTime(session.last_seen_at),
+            expires_at: CustomDateTime(session.expires_at),
+            revoked_at: session.revoked_at.map(CustomDateTime),
+        }
+    }
+}
+
+impl From<PersonalAccessToken> for ExportPersonalAccessToken {
+    fn from(token: PersonalAccessToken) -> Self {
+        ExportPersonalAccessToken {
+            id: token.id,
+            name: token.name,
+            scopes: token.scopes,
+            created_at: CustomDateTime(token.created_at),
+            last_used_at: token.last_used_at.map(CustomDateTime),
+            expires_at: token.expires_at.map(CustomDateTime),
+            revoked_at: token.revoked_at.map(CustomDateTime),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use std::cmp;
+use uuid::Uuid;
+
+use super::PooledConn;
+use crate::models::{LoginThrottle, NewLoginThrottle};
+use crate::prelude::*;
+use crate::utils::auth::ClientInfo;
+
+// the first lockout lasts this long and doubles with every further failed attempt
+const LOCKOUT_BASE_SECONDS: i64 = 30;
+const LOCKOUT_MAX_SECONDS: i64 = 60 * 60;
+// failures are forgotten once nothing has failed for this long
+const FAILURE_WINDOW_HOURS: i64 = 24;
+
+// One counter of failed logins, either for an account or for an IP address, or of requests for
+// password reset mails
+#[derive(Debug)]
+pub struct ThrottleKey {
+    key: String,
+    // failed attempts allowed before lockouts kick in
+    free_attempts: i32,
+}
+
+impl ThrottleKey {
+    // the user's id, or what was typed in when no such user exists
+    pub fn account(account: &str) -> Self {
+        ThrottleKey {
+            key: format!("account:{}", account.trim().to_lowercase()),
+            free_attempts: 5,
+        }
+    }
+
+    // codes for the second step of a login are counted apart from passwords, logging in with the
+    // right password clears the account's counter and mustn't give a code guesser a fresh start
+    pub fn second_factor(user_id: Uuid) -> Self {
+        ThrottleKey {
+            key: format!("second_factor:{}", user_id),
+            free_attempts: 5,
+        }
+    }
+
+    // an address can be shared by many users, e.g. behind a NAT, so it gets more leeway
+    pub fn ip_address(ip_address: &str) -> Self {
+        ThrottleKey {
+            key: format!("ip:{}", ip_address),
+            free_attempts: 20,
+        }
+    }
+
+    // every request sends a mail, to an address that may not even belong to whoever asks
+    pub fn password_reset(email: &str) -> Self {
+        ThrottleKey {
+            key: format!("password_reset:{}", email.trim().to_lowercase()),
+            free_attempts: 3,
+        }
+    }
+
+    // counted apart from failed logins, asking for a reset mustn't lock out logging in
+    pub fn password_reset_ip_address(ip_address: &str) -> Self {
+        ThrottleKey {
+            key: format!("password_reset_ip:{}", ip_address),
+            free_attempts: 20,
+        }
+    }
+
+    fn lockout(&self, failed_attempts: i32) -> Option<Duration> {
+        let over_limit = failed_attempts - self.free_attempts;
+        if over_limit <= 0 {
+            return None;
+        }
+        // capping the exponent first keeps the shift from overflowing
+        let seconds = LOCKOUT_BASE_SECONDS << cmp::min(over_limit - 1, 16);
+        Some(Duration::seconds(cmp::min(seconds, LOCKOUT_MAX_SECONDS)))
+    }
+}
+
+pub fn login_throttle_keys(account: &str, client: &ClientInfo) -> Vec<ThrottleKey> {
+    with_ip_address(ThrottleKey::account(account), client)
+}
+
+pub fn second_factor_throttle_keys(user_id: Uuid, client: &ClientInfo) -> Vec<ThrottleKey> {
+    with_ip_address(ThrottleKey::second_factor(user_id), client)
+}
+
+pub fn password_reset_throttle_keys(email: &str, client: &ClientInfo) -> Vec<ThrottleKey> {
+    let mut keys = vec![ThrottleKey::password_reset(email)];
+    if let Some(ref ip_address) = client.ip_address {
+        keys.push(ThrottleKey::password_reset_ip_address(ip_address));
+    }
+    keys
+}
+
+fn with_ip_address(key: ThrottleKey, client: &ClientInfo) -> Vec<ThrottleKey> {
+    let mut keys = vec![key];
+    if let Some(ref ip_address) = client.ip_address {
+        keys.push(ThrottleKey::ip_address(ip_address));
+    }
+    keys
+}
+
+// Refuses the attempt outright while the account or the address is locked out
+pub fn check_login_throttle(keys: &[ThrottleKey], conn: &PooledConn) -> Result<()> {
+    check_throttle(keys, "Too many failed login attempts, try again later", conn)
+}
+
+pub fn check_password_reset_throttle(keys: &[ThrottleKey], conn: &PooledConn) -> Result<()> {
+    check_throttle(keys, "Too many password reset requests, try again later", conn)
+}
+
+fn check_throttle(keys: &[ThrottleKey], error: &str, conn: &PooledConn) -> Result<()> {
+    use crate::schema::login_throttles;
+
+    let now = Utc::now().naive_utc();
+    let locked_until = login_throttles::table
+        .filter(login_throttles::key.eq_any(keys.iter().map(|key| &key.key)))
+        .filter(login_throttles::locked_until.gt(now))
+        .select(login_throttles::locked_until)
+        .load::<Option<NaiveDateTime>>(conn)?
+        .into_iter()
+        .flatten()
+        .max();
+
+    match locked_until {
+        Some(locked_until) => Err(Error::TooManyRequests {
+            message: json!({ "error": error }),
+            // round up so retrying right after the wait doesn't hit the lockout again
+            retry_after: (locked_until - now).num_seconds() + 1,
+        }),
+        None => Ok(()),
+    }
+}
+
+pub fn record_failed_login(keys: &[ThrottleKey], conn: &PooledConn) -> Result<()> {
+    use crate::schema::login_throttles;
+
+    let now = Utc::now().naive_utc();
+
+    conn.transaction::<_, Error, _>(|| {
+        for key in keys {
+            diesel::insert_into(login_throttles::table)
+                .values(NewLoginThrottle { key: &key.key })
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            // lock the row so concurrent failures are all counted
+            let throttle = login_throttles::table
+                .find(&key.key)
+                .for_update()
+                .get_result::<LoginThrottle>(conn)?;
+
+            let window_passed = now - throttle.last_failed_at > Duration::hours(FAILURE_WINDOW_HOURS);
+            let failed_attempts = if window_passed { 1 } else { throttle.failed_attempts + 1 };
+            let locked_until = key.lockout(failed_attempts).map(|lockout| now + lockout);
+
+            diesel::update(&throttle)
+                .set((
+                    login_throttles::failed_attempts.eq(failed_attempts),
+                    login_throttles::last_failed_at.eq(now),
+                    login_throttles::locked_until.eq(locked_until),
+                ))
+                .execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
+// Only the account's counter is cleared, otherwise logging into an account of one's own would
+// reset the address's counter as well
+pub fn clear_login_throttle(key: &ThrottleKey, conn: &PooledConn) -> Result<()> {
+    use crate::schema::login_throttles;
+
+    diesel::delete(login_throttles::table.find(&key.key)).execute(conn)?;
+
+    Ok(())
+}
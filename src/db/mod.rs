@@ -1,8 +1,20 @@
+mod admin;
 mod articles;
+mod audit;
 mod auth;
 mod comments;
+mod email_verifications;
+mod export;
+mod login_throttles;
+mod oidc;
+mod password_resets;
 mod profiles;
+mod revisions;
+mod search;
+mod sessions;
 mod tags;
+mod tokens;
+mod totp;
 mod users;
 
 use crate::prelude::*;
@@ -10,12 +22,17 @@ use actix::prelude::{Actor, SyncContext};
 use diesel::{
     pg::PgConnection,
     r2d2::{self, ConnectionManager, Pool, PooledConnection},
+    sql_types::Text,
 };
 
 pub type Conn = PgConnection;
 pub type PgPool = Pool<ConnectionManager<Conn>>;
 pub type PooledConn = PooledConnection<ConnectionManager<Conn>>;
 
+// usernames and emails are unique regardless of case, compare lower(column) with a lowercased
+// value so lookups agree with the unique indexes on users and can use them
+sql_function!(fn lower(x: Text) -> Text);
+
 pub struct DbExecutor(pub PgPool);
 
 impl Actor for DbExecutor {
@@ -0,0 +1,393 @@
+use actix::prelude::*;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+
+use super::{
+    audit::{record_audit_event, AuditTarget},
+    login_throttles::{
+        check_login_throttle, clear_login_throttle, login_throttle_keys, record_failed_login,
+        second_factor_throttle_keys,
+    },
+    lower,
+    sessions::start_session,
+    totp::{check_second_factor, start_session_or_challenge},
+    DbExecutor, PooledConn,
+};
+use crate::app::oidc::{
+    ConfirmLinkOuter, ConsumeOidcLogin, IdentityResponse, IdentityResponseInner, LinkResponse,
+    LinkResponseInner, OidcLogin, OidcSignIn, OidcSignInResponse, StartOidcLogin,
+};
+use crate::app::users::LoginResponse;
+use crate::models::{
+    Identity, NewIdentity, NewOidcLoginState, NewOidcPendingLink, NewUser, OidcLoginState,
+    OidcPendingLink, User,
+};
+use crate::prelude::*;
+use crate::utils::{
+    oidc::{IdTokenClaims, LOGIN_LIFETIME_MINUTES},
+    token::{generate_token, hash_token},
+    CustomDateTime, HASHER,
+};
+
+// how long the owner of an account has to confirm linking an identity to it
+const LINK_LIFETIME_MINUTES: i64 = 15;
+const USERNAME_MAX_LENGTH: usize = 20;
+
+// message handler implementations ↓
+
+impl Message for StartOidcLogin {
+    type Result = Result<OidcLogin>;
+}
+
+impl Handler<StartOidcLogin> for DbExecutor {
+    type Result = Result<OidcLogin>;
+
+    fn handle(&mut self, msg: StartOidcLogin, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::oidc_login_states;
+
+        let conn = &self.0.get()?;
+        let now = Utc::now().naive_utc();
+
+        // sign-ins that were never finished
+        diesel::delete(oidc_login_states::table.filter(oidc_login_states::expires_at.lt(now)))
+            .execute(conn)?;
+
+        let login = OidcLogin {
+            state: generate_token(),
+            nonce: generate_token(),
+            code_verifier: generate_token(),
+            binding: generate_token(),
+        };
+
+        diesel::insert_into(oidc_login_states::table)
+            .values(NewOidcLoginState {
+                provider: msg.provider,
+                state_hash: hash_token(&login.state),
+                nonce: login.nonce.to_owned(),
+                code_verifier: login.code_verifier.to_owned(),
+                expires_at: now + Duration::minutes(LOGIN_LIFETIME_MINUTES),
+                binding_hash: hash_token(&login.binding),
+            })
+            .execute(conn)?;
+
+        Ok(login)
+    }
+}
+
+impl Message for ConsumeOidcLogin {
+    type Result = Result<OidcLogin>;
+}
+
+impl Handler<ConsumeOidcLogin> for DbExecutor {
+    type Result = Result<OidcLogin>;
+
+    fn handle(&mut self, msg: ConsumeOidcLogin, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::oidc_login_states;
+
+        let conn = &self.0.get()?;
+
+        // deleting it makes sure each sign-in can only be finished once, and only in the browser
+        // that started it, so nobody can slip their own sign-in into someone else's browser
+        let login_state = diesel::delete(
+            oidc_login_states::table
+                .filter(oidc_login_states::state_hash.eq(hash_token(&msg.state)))
+                .filter(oidc_login_states::binding_hash.eq(hash_token(&msg.binding)))
+                .filter(oidc_login_states::provider.eq(&msg.provider))
+                .filter(oidc_login_states::expires_at.gt(Utc::now().naive_utc())),
+        )
+        .get_result::<OidcLoginState>(conn)
+        .optional()?
+        .ok_or_else(|| {
+            Error::Unauthorized(json!({
+                "error": "This sign-in has expired or was already used, please start over",
+            }))
+        })?;
+
+        Ok(OidcLogin {
+            state: msg.state,
+            nonce: login_state.nonce,
+            code_verifier: login_state.code_verifier,
+            binding: msg.binding,
+        })
+    }
+}
+
+impl Message for OidcSignIn {
+    type Result = Result<OidcSignInResponse>;
+}
+
+impl Handler<OidcSignIn> for DbExecutor {
+    type Result = Result<OidcSignInResponse>;
+
+    fn handle(&mut self, msg: OidcSignIn, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::{identities, oidc_pending_links, users};
+
+        let conn = &self.0.get()?;
+
+        conn.transaction::<_, Error, _>(|| {
+            let known_user = identities::table
+                .inner_join(users::table)
+                .filter(identities::provider.eq(&msg.provider))
+                .filter(identities::subject.eq(&msg.claims.sub))
+                .get_result::<(Identity, User)>(conn)
+                .optional()?;
+
+            if let Some((identity, user)) = known_user {
+                if identity.email != msg.claims.email {
+                    diesel::update(&identity)
+                        .set(identities::email.eq(&msg.claims.email))
+                        .execute(conn)?;
+                }
+                // the provider vouches for the first factor, the account's own second factor
+                // still applies
+                return Ok(OidcSignInResponse::SignedIn(start_session_or_challenge(
+                    user, msg.client, conn,
+                )?));
+            }
+
+            if let Some(user) = find_user_to_link(&msg.claims, conn)? {
+                let now = Utc::now().naive_utc();
+
+                // links that were never confirmed
+                diesel::delete(
+                    oidc_pending_links::table.filter(oidc_pending_links::expires_at.lt(now)),
+                )
+                .execute(conn)?;
+
+                let token = generate_token();
+                diesel::insert_into(oidc_pending_links::table)
+                    .values(NewOidcPendingLink {
+                        user_id: user.id,
+                        provider: msg.provider.to_owned(),
+                        subject: msg.claims.sub.to_owned(),
+                        email: msg.claims.email.to_owned(),
+                        token_hash: hash_token(&token),
+                        expires_at: now + Duration::minutes(LINK_LIFETIME_MINUTES),
+                    })
+                    .execute(conn)?;
+
+                return Ok(OidcSignInResponse::LinkRequired(LinkResponse {
+                    link: LinkResponseInner {
+                        token,
+                        provider: msg.provider,
+                        email: msg.claims.email,
+                    },
+                }));
+            }
+
+            let user = create_user(&msg.claims, conn)?;
+
+            diesel::insert_into(identities::table)
+                .values(NewIdentity {
+                    user_id: user.id,
+                    provider: msg.provider.to_owned(),
+                    subject: msg.claims.sub.to_owned(),
+                    email: msg.claims.email.to_owned(),
+                })
+                .execute(conn)?;
+
+            log::info!("signed up user {} with {} account {}", user.username, msg.provider, msg.claims.sub);
+
+            Ok(OidcSignInResponse::SignedIn(LoginResponse::Authenticated(
+                start_session(user, msg.client, conn)?,
+            )))
+        })
+    }
+}
+
+impl Message for ConfirmLinkOuter {
+    type Result = Result<IdentityResponse>;
+}
+
+impl Handler<ConfirmLinkOuter> for DbExecutor {
+    type Result = Result<IdentityResponse>;
+
+    fn handle(&mut self, msg: ConfirmLinkOuter, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::{identities, oidc_pending_links};
+
+        let user = msg.auth.user;
+        let client = msg.auth.client;
+        let confirm_link = msg.confirm_link;
+
+        let conn = &self.0.get()?;
+
+        // the same counters as logging in, a stolen session mustn't be a way to guess the password
+        let throttle_keys = login_throttle_keys(&user.id.to_string(), &client);
+        check_login_throttle(&throttle_keys, conn)?;
+
+        let link_not_found = || {
+            Error::NotFound(json!({
+                "error": "This link has expired or was already confirmed, sign in with the provider again",
+            }))
+        };
+
+        let pending_link = oidc_pending_links::table
+            .filter(oidc_pending_links::token_hash.eq(hash_token(&confirm_link.link_token)))
+            .filter(oidc_pending_links::user_id.eq(user.id))
+            .filter(oidc_pending_links::expires_at.gt(Utc::now().naive_utc()))
+            .get_result::<OidcPendingLink>(conn)
+            .optional()?
+            .ok_or_else(link_not_found)?;
+
+        if !HASHER.verify(&user.password, &confirm_link.password)? {
+            record_failed_login(&throttle_keys, conn)?;
+            return Err(Error::UnprocessableEntity(json!({
+                "errors": { "password": ["is incorrect"] },
+            })));
+        }
+
+        if user.totp_enabled_at.is_some() {
+            let code = confirm_link.code.ok_or_else(|| {
+                Error::UnprocessableEntity(json!({
+                    "errors": { "code": ["is required for accounts with two-factor authentication"] },
+                }))
+            })?;
+
+            let second_factor_keys = second_factor_throttle_keys(user.id, &client);
+            check_login_throttle(&second_factor_keys, conn)?;
+
+            if !check_second_factor(&user, &code, conn)? {
+                record_failed_login(&second_factor_keys, conn)?;
+                return Err(Error::UnprocessableEntity(json!({
+                    "errors": { "code": ["is incorrect"] },
+                })));
+            }
+            clear_login_throttle(&second_factor_keys[0], conn)?;
+        }
+        clear_login_throttle(&throttle_keys[0], conn)?;
+
+        let identity = conn.transaction::<_, Error, _>(|| {
+            // deleting it makes sure each link can only be confirmed once
+            let pending_link = diesel::delete(&pending_link)
+                .get_result::<OidcPendingLink>(conn)
+                .optional()?
+                .ok_or_else(link_not_found)?;
+
+            let identity = diesel::insert_into(identities::table)
+                .values(NewIdentity {
+                    user_id: user.id,
+                    provider: pending_link.provider,
+                    subject: pending_link.subject,
+                    email: pending_link.email,
+                })
+                .get_result::<Identity>(conn)?;
+
+            record_audit_event(
+                conn,
+                Some(user.id),
+                &client,
+                "user.identity_linked",
+                Some(AuditTarget::User(user.id)),
+                json!({ "provider": identity.provider, "subject": identity.subject }),
+            )?;
+
+            Ok(identity)
+        })?;
+
+        log::info!("linked {} account {} to user {}", identity.provider, identity.subject, user.username);
+
+        Ok(IdentityResponse {
+            identity: IdentityResponseInner {
+                provider: identity.provider,
+                email: identity.email,
+                created_at: CustomDateTime(identity.created_at),
+            },
+        })
+    }
+}
+
+// local helper methods ↓
+
+// An existing account is only offered for linking when the provider vouches for the email
+// address, and even then its owner has to confirm it, so an identity provider that gets an
+// address wrong can't hand out access to someone else's account
+fn find_user_to_link(claims: &IdTokenClaims, conn: &PooledConn) -> Result<Option<User>> {
+    use crate::schema::users;
+
+    match (&claims.email, claims.email_verified) {
+        (Some(email), Some(true)) => Ok(users::table
+            .filter(lower(users::email).eq(email.to_lowercase()))
+            .get_result::<User>(conn)
+            .optional()?),
+        _ => Ok(None),
+    }
+}
+
+fn create_user(claims: &IdTokenClaims, conn: &PooledConn) -> Result<User> {
+    use crate::schema::users;
+
+    let email = claims.email.to_owned().ok_or_else(|| {
+        Error::UnprocessableEntity(json!({
+            "error": "The identity provider didn't share an email address",
+        }))
+    })?;
+
+    let email_taken = users::table
+        .filter(lower(users::email).eq(email.to_lowercase()))
+        .count()
+        .get_result::<i64>(conn)?
+        > 0;
+    if email_taken {
+        return Err(Error::UnprocessableEntity(json!({
+            "error": "An account with this email already exists, sign in with its password instead",
+        })));
+    }
+
+    let username = available_username(claims, conn)?;
+
+    let user = diesel::insert_into(users::table)
+        .values(NewUser {
+            username,
+            email,
+            // a random password nobody knows, one can still be set through a password reset
+            password: HASHER.hash(&generate_token())?,
+            bio: None,
+            image: None,
+        })
+        .get_result::<User>(conn)?;
+
+    if claims.email_verified == Some(true) {
+        return Ok(diesel::update(&user)
+            .set(users::email_verified_at.eq(Utc::now().naive_utc()))
+            .get_result::<User>(conn)?);
+    }
+
+    Ok(user)
+}
+
+// Derives a username that fits our rules from what the provider knows, adding a random suffix
+// until it's free
+fn available_username(claims: &IdTokenClaims, conn: &PooledConn) -> Result<String> {
+    use crate::schema::users;
+
+    let preferred = claims
+        .preferred_username
+        .as_ref()
+        .or(claims.email.as_ref())
+        .map(|name| name.split('@').next().unwrap_or_default())
+        .unwrap_or_default();
+    let mut base: String = preferred
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .take(USERNAME_MAX_LENGTH)
+        .collect();
+    if base.is_empty() {
+        base = "user".into();
+    }
+
+    let mut candidate = base.to_owned();
+    loop {
+        let taken = users::table
+            .filter(lower(users::username).eq(candidate.to_lowercase()))
+            .count()
+            .get_result::<i64>(conn)?
+            > 0;
+        if !taken {
+            return Ok(candidate);
+        }
+
+        let suffix = &generate_token()[..4];
+        let kept = std::cmp::min(base.len(), USERNAME_MAX_LENGTH - suffix.len());
+        candidate = format!("{}{}", &base[..kept], suffix);
+    }
+}
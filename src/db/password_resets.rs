@@ -0,0 +1,150 @@
+use actix::prelude::*;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+
+use super::{
+    audit::{record_audit_event, AuditTarget},
+    login_throttles::{
+        check_password_reset_throttle, password_reset_throttle_keys, record_failed_login,
+    },
+    lower,
+    sessions::revoke_all_sessions,
+    tokens::revoke_all_tokens,
+    DbExecutor, PooledConn,
+};
+use crate::app::users::{ConfirmPasswordResetOuter, RequestPasswordResetOuter};
+use crate::models::{NewPasswordResetToken, PasswordResetToken, User};
+use crate::prelude::*;
+use crate::utils::{
+    mailer::{Mail, MAILER},
+    password_policy::PASSWORD_POLICY,
+    token::{generate_token, hash_token},
+    HASHER,
+};
+
+pub const PASSWORD_RESET_LIFETIME_HOURS: i64 = 1;
+
+// message handler implementations ↓
+
+impl Message for RequestPasswordResetOuter {
+    type Result = Result<()>;
+}
+
+impl Handler<RequestPasswordResetOuter> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: RequestPasswordResetOuter, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::users;
+
+        let email = msg.request_password_reset.email;
+
+        let conn = &self.0.get()?;
+
+        // every request counts, not just failed ones, so nobody can flood a mailbox with them
+        let throttle_keys = password_reset_throttle_keys(&email, &msg.client);
+        check_password_reset_throttle(&throttle_keys, conn)?;
+        record_failed_login(&throttle_keys, conn)?;
+
+        // Whether or not the email belongs to anyone, the response is the same,
+        // so this can't be used to find out who has an account
+        match users::table
+            .filter(lower(users::email).eq(email.to_lowercase()))
+            .first::<User>(conn)
+            .optional()?
+        {
+            Some(user) => send_password_reset(&user, conn),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Message for ConfirmPasswordResetOuter {
+    type Result = Result<()>;
+}
+
+impl Handler<ConfirmPasswordResetOuter> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: ConfirmPasswordResetOuter, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::{password_reset_tokens, users};
+
+        let client = msg.client;
+        let confirm_password_reset = msg.confirm_password_reset;
+
+        let conn = &self.0.get()?;
+
+        let new_password = HASHER.hash(&confirm_password_reset.password)?;
+
+        conn.transaction::<_, Error, _>(|| {
+            let now = Utc::now().naive_utc();
+
+            let reset_token = password_reset_tokens::table
+                .filter(password_reset_tokens::token_hash.eq(hash_token(&confirm_password_reset.token)))
+                .filter(password_reset_tokens::used_at.is_null())
+                .filter(password_reset_tokens::expires_at.gt(now))
+                .for_update()
+                .get_result::<PasswordResetToken>(conn)
+                .optional()?
+                .ok_or_else(|| {
+                    Error::UnprocessableEntity(json!({
+                        "errors": { "token": ["is invalid or has expired"] },
+                    }))
+                })?;
+
+            let user = users::table.find(reset_token.user_id).get_result::<User>(conn)?;
+            PASSWORD_POLICY.check(&confirm_password_reset.password, &user.username, &user.email)?;
+
+            diesel::update(users::table.find(reset_token.user_id))
+                .set(users::password.eq(new_password))
+                .execute(conn)?;
+
+            // the token just used, and any other one still lying around in a mailbox
+            diesel::update(password_reset_tokens::table)
+                .filter(password_reset_tokens::user_id.eq(reset_token.user_id))
+                .filter(password_reset_tokens::used_at.is_null())
+                .set(password_reset_tokens::used_at.eq(now))
+                .execute(conn)?;
+
+            // whoever may have known the old password shouldn't stay signed in, or keep a token
+            // they made with it
+            revoke_all_sessions(reset_token.user_id, conn)?;
+            let revoked_tokens = revoke_all_tokens(reset_token.user_id, conn)?;
+
+            record_audit_event(
+                conn,
+                Some(user.id),
+                &client,
+                "user.password_changed",
+                Some(AuditTarget::User(user.id)),
+                json!({ "via": "reset", "revokedTokens": revoked_tokens }),
+            )
+        })
+    }
+}
+
+// helper methods ↓
+
+pub fn send_password_reset(user: &User, conn: &PooledConn) -> Result<()> {
+    use crate::schema::password_reset_tokens;
+
+    let token = generate_token();
+
+    diesel::insert_into(password_reset_tokens::table)
+        .values(NewPasswordResetToken {
+            user_id: user.id,
+            token_hash: hash_token(&token),
+            expires_at: (Utc::now() + Duration::hours(PASSWORD_RESET_LIFETIME_HOURS)).naive_utc(),
+        })
+        .execute(conn)?;
+
+    MAILER.send(&Mail {
+        to: user.email.to_owned(),
+        subject: "Reset your password".into(),
+        body: format!(
+            "Someone asked to reset the password of your account {}.\r\n\r\n\
+             Use this token within {} hour to choose a new password:\r\n\r\n{}\r\n\r\n\
+             If this wasn't you, you can ignore this mail.",
+            user.username, PASSWORD_RESET_LIFETIME_HOURS, token
+        ),
+    })
+}
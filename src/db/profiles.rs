@@ -1,12 +1,13 @@
 use actix::prelude::*;
-use diesel::prelude::*;
+use diesel::{prelude::*, result::Error as DieselError};
 
-use super::DbExecutor;
+use super::{lower, DbExecutor};
 use crate::app::profiles::{
     FollowProfile, GetProfile, ProfileResponse, ProfileResponseInner, UnfollowProfile,
 };
 use crate::models::{Follower, NewFollower, User};
 use crate::prelude::*;
+use crate::utils::permissions::{require_permission, Permission};
 
 // message handler implementations ↓
 
@@ -22,9 +23,18 @@ impl Handler<GetProfile> for DbExecutor {
 
         let user: User = {
             use crate::schema::users::dsl::*;
-            users.filter(username.eq(msg.username)).first(conn)?
+            users.filter(lower(username).eq(msg.username.to_lowercase())).first(conn)?
         };
 
+        // suspended users are gone as far as anyone but admins can tell
+        let viewer_manages_users = match msg.auth {
+            Some(ref auth) => require_permission(&auth.user, Permission::ManageUsers).is_ok(),
+            None => false,
+        };
+        if user.suspended_at.is_some() && !viewer_manages_users {
+            return Err(DieselError::NotFound.into());
+        }
+
         use crate::schema::followers::dsl::*;
 
         let following = match msg.auth {
@@ -60,7 +70,7 @@ impl Handler<FollowProfile> for DbExecutor {
 
         let user_a: User = {
             use crate::schema::users::dsl::*;
-            users.filter(username.eq(msg.username)).first(conn)?
+            users.filter(lower(username).eq(msg.username.to_lowercase())).first(conn)?
         };
         let user_b: User = msg.auth.user;
 
@@ -102,7 +112,7 @@ impl Handler<UnfollowProfile> for DbExecutor {
 
         let user_a: User = {
             use crate::schema::users::dsl::*;
-            users.filter(username.eq(msg.username)).first(conn)?
+            users.filter(lower(username).eq(msg.username.to_lowercase())).first(conn)?
         };
         let user_b: User = msg.auth.user;
 
@@ -0,0 +1,218 @@
+use actix::prelude::*;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::{
+    articles::{find_visible_article, generate_slug, get_article_response},
+    DbExecutor, PooledConn,
+};
+use crate::app::articles::revisions::{
+    DiffResponse, DiffResponseInner, GetRevision, GetRevisionDiff, GetRevisions, RestoreRevision,
+    RevisionListResponse, RevisionResponse, RevisionResponseInner,
+};
+use crate::app::articles::ArticleResponse;
+use crate::models::{Article, ArticleChange, ArticleRevision, NewArticleRevision};
+use crate::prelude::*;
+use crate::utils::{
+    diff::diff_lines,
+    markdown::render_markdown,
+    permissions::{authorize, Permission},
+    CustomDateTime,
+};
+
+// message handler implementations ↓
+
+impl Message for GetRevisions {
+    type Result = Result<RevisionListResponse>;
+}
+
+impl Handler<GetRevisions> for DbExecutor {
+    type Result = Result<RevisionListResponse>;
+
+    fn handle(&mut self, msg: GetRevisions, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::article_revisions;
+
+        let conn = &self.0.get()?;
+
+        // the history may hold what the author took out again, so it's for those who can edit
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
+        authorize(&msg.auth.user, article.author_id, Permission::EditArticle, &article.slug)?;
+
+        let revisions = article_revisions::table
+            .filter(article_revisions::article_id.eq(article.id))
+            .order(article_revisions::number.desc())
+            .load::<ArticleRevision>(conn)?
+            .into_iter()
+            .map(RevisionResponseInner::from)
+            .collect::<Vec<_>>();
+
+        Ok(RevisionListResponse {
+            revisions_count: revisions.len(),
+            revisions,
+        })
+    }
+}
+
+impl Message for GetRevision {
+    type Result = Result<RevisionResponse>;
+}
+
+impl Handler<GetRevision> for DbExecutor {
+    type Result = Result<RevisionResponse>;
+
+    fn handle(&mut self, msg: GetRevision, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
+        authorize(&msg.auth.user, article.author_id, Permission::EditArticle, &article.slug)?;
+
+        let revision = find_revision(article.id, msg.number, conn)?;
+
+        Ok(RevisionResponse {
+            revision: revision.into(),
+        })
+    }
+}
+
+impl Message for GetRevisionDiff {
+    type Result = Result<DiffResponse>;
+}
+
+impl Handler<GetRevisionDiff> for DbExecutor {
+    type Result = Result<DiffResponse>;
+
+    fn handle(&mut self, msg: GetRevisionDiff, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
+        authorize(&msg.auth.user, article.author_id, Permission::EditArticle, &article.slug)?;
+
+        let from = find_revision(article.id, msg.number, conn)?;
+        let (title, description, body) = match msg.params.to {
+            Some(number) => {
+                let to = find_revision(article.id, number, conn)?;
+                (to.title, to.description, to.body)
+            }
+            None => (article.title, article.description, article.body),
+        };
+
+        Ok(DiffResponse {
+            diff: DiffResponseInner {
+                from: from.number,
+                to: msg.params.to,
+                title: diff_lines(&from.title, &title),
+                description: diff_lines(&from.description, &description),
+                body: diff_lines(&from.body, &body),
+            },
+        })
+    }
+}
+
+impl Message for RestoreRevision {
+    type Result = Result<ArticleResponse>;
+}
+
+impl Handler<RestoreRevision> for DbExecutor {
+    type Result = Result<ArticleResponse>;
+
+    fn handle(&mut self, msg: RestoreRevision, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::articles;
+
+        let conn = &self.0.get()?;
+
+        let article = find_visible_article(&msg.slug, Some(msg.auth.user.id), conn)?;
+        if article.author_id != msg.auth.user.id {
+            return Err(Error::Forbidden(json!({
+                "error": "only the author can restore a revision",
+            })));
+        }
+
+        let revision = find_revision(article.id, msg.number, conn)?;
+
+        // restoring is just another edit, the version it replaces becomes a revision of its own
+        let article = conn.transaction::<_, Error, _>(|| {
+            let unchanged = revision.title == article.title
+                && revision.description == article.description
+                && revision.body == article.body;
+            if unchanged {
+                return Ok(article);
+            }
+
+            save_revision(article.id, conn)?;
+
+            let slug = match revision.title != article.title {
+                true => Some(generate_slug(&article.id, &revision.title)),
+                false => None,
+            };
+
+            diesel::update(articles::table.find(article.id))
+                .set(&ArticleChange {
+                    slug,
+                    title: Some(revision.title),
+                    description: Some(revision.description),
+                    body_html: Some(render_markdown(&revision.body)),
+                    body: Some(revision.body),
+                    status: None,
+                    published_at: None,
+                    publish_at: None,
+                })
+                .get_result::<Article>(conn)
+                .map_err(Into::into)
+        })?;
+
+        get_article_response(article.slug, Some(msg.auth.user.id), conn)
+    }
+}
+
+impl From<ArticleRevision> for RevisionResponseInner {
+    fn from(revision: ArticleRevision) -> Self {
+        RevisionResponseInner {
+            number: revision.number,
+            title: revision.title,
+            description: revision.description,
+            body: revision.body,
+            created_at: CustomDateTime(revision.created_at),
+        }
+    }
+}
+
+// helper methods ↓
+
+// Keeps the article as it is right now as its next revision. Call this inside the transaction that
+// changes the article: the row stays locked until then, so two edits can't take the same number.
+pub fn save_revision(article_id: Uuid, conn: &PooledConn) -> Result<()> {
+    use crate::schema::{article_revisions, articles};
+
+    let article = articles::table
+        .find(article_id)
+        .for_update()
+        .get_result::<Article>(conn)?;
+
+    let last_number = article_revisions::table
+        .filter(article_revisions::article_id.eq(article.id))
+        .select(diesel::dsl::max(article_revisions::number))
+        .get_result::<Option<i32>>(conn)?;
+
+    diesel::insert_into(article_revisions::table)
+        .values(NewArticleRevision {
+            article_id: article.id,
+            number: last_number.unwrap_or(0) + 1,
+            title: article.title,
+            description: article.description,
+            body: article.body,
+        })
+        .execute(conn)?;
+
+    Ok(())
+}
+
+// local helper methods ↓
+
+fn find_revision(article_id: Uuid, number: i32, conn: &PooledConn) -> Result<ArticleRevision> {
+    use crate::schema::article_revisions;
+
+    article_revisions::table
+        .find((article_id, number))
+        .get_result::<ArticleRevision>(conn)
+        .map_err(Into::into)
+}
@@ -0,0 +1,140 @@
+use actix::prelude::*;
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Text},
+};
+
+use super::{articles::get_article_list_response, DbExecutor};
+use crate::app::articles::{search::SearchArticles, ArticleListResponse};
+use crate::models::{Article, ArticleStatus};
+use crate::prelude::*;
+use crate::utils::pagination::PageLinks;
+
+// ts_headline marks matches with these, they can't be in the article text once it's escaped
+const MATCH_START: &str = "\u{2}";
+const MATCH_END: &str = "\u{3}";
+
+const MAX_QUERY_LENGTH: usize = 200;
+
+const COUNT_QUERY: &str = "
+    SELECT count(*) AS count
+    FROM articles
+    INNER JOIN article_search ON article_search.article_id = articles.id
+    INNER JOIN users ON users.id = articles.author_id
+    CROSS JOIN websearch_to_tsquery('english', $1) AS query
+    WHERE article_search.document @@ query
+        AND articles.status = $2
+        AND users.suspended_at IS NULL
+";
+
+// ranked by how well the document matches, newest first among equals. Only published articles by
+// authors who aren't suspended are found, like in the article list.
+const SEARCH_QUERY: &str = "
+    SELECT articles.*,
+        ts_headline('english', articles.description || E'\\n' || articles.body, query, $2) AS snippet
+    FROM articles
+    INNER JOIN article_search ON article_search.article_id = articles.id
+    INNER JOIN users ON users.id = articles.author_id
+    CROSS JOIN websearch_to_tsquery('english', $1) AS query
+    WHERE article_search.document @@ query
+        AND articles.status = $3
+        AND users.suspended_at IS NULL
+    ORDER BY ts_rank(article_search.document, query) DESC, articles.published_at DESC, articles.id
+    LIMIT $4 OFFSET $5
+";
+
+#[derive(QueryableByName)]
+struct SearchCount {
+    #[sql_type = "BigInt"]
+    count: i64,
+}
+
+#[derive(QueryableByName)]
+struct SearchHit {
+    #[diesel(embed)]
+    article: Article,
+    #[sql_type = "Text"]
+    snippet: String,
+}
+
+// message handler implementations ↓
+
+impl Message for SearchArticles {
+    type Result = Result<ArticleListResponse>;
+}
+
+impl Handler<SearchArticles> for DbExecutor {
+    type Result = Result<ArticleListResponse>;
+
+    fn handle(&mut self, msg: SearchArticles, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        let q = match msg.params.q {
+            Some(ref q) if !q.trim().is_empty() => q.trim(),
+            _ => {
+                return Err(Error::UnprocessableEntity(json!({
+                    "errors": { "q": ["can't be blank"] },
+                })))
+            }
+        };
+        if q.chars().count() > MAX_QUERY_LENGTH {
+            return Err(Error::UnprocessableEntity(json!({
+                "errors": { "q": [format!("can't be longer than {} characters", MAX_QUERY_LENGTH)] },
+            })));
+        }
+
+        let limit = std::cmp::min(msg.params.limit.unwrap_or(20), 100) as i64;
+        let offset = msg.params.offset.unwrap_or(0) as i64;
+        let headline_options = format!(
+            "StartSel={}, StopSel={}, MaxFragments=2, MaxWords=30, MinWords=10",
+            MATCH_START, MATCH_END
+        );
+
+        let articles_count = diesel::sql_query(COUNT_QUERY)
+            .bind::<Text, _>(q)
+            .bind::<Text, _>(ArticleStatus::Published.as_str())
+            .get_result::<SearchCount>(conn)?
+            .count;
+
+        let hits = diesel::sql_query(SEARCH_QUERY)
+            .bind::<Text, _>(q)
+            .bind::<Text, _>(headline_options)
+            .bind::<Text, _>(ArticleStatus::Published.as_str())
+            .bind::<BigInt, _>(limit)
+            .bind::<BigInt, _>(offset)
+            .load::<SearchHit>(conn)?;
+
+        let (matched_articles, snippets): (Vec<_>, Vec<_>) = hits
+            .into_iter()
+            .map(|hit| (hit.article, hit.snippet))
+            .unzip();
+
+        let user_id = msg.auth.map(|auth| auth.user.id);
+        let mut list = get_article_list_response(
+            matched_articles,
+            articles_count,
+            PageLinks::default(),
+            user_id,
+            conn,
+        )?;
+        for (article, snippet) in list.articles.iter_mut().zip(snippets) {
+            article.snippet = Some(highlight(&snippet));
+        }
+
+        Ok(list)
+    }
+}
+
+// local helper methods ↓
+
+// The snippet is raw article text, so it's escaped before the matches are wrapped in <mark>
+fn highlight(snippet: &str) -> String {
+    snippet
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+        .replace(MATCH_START, "<mark>")
+        .replace(MATCH_END, "</mark>")
+}
@@ -0,0 +1,263 @@
+use actix::prelude::*;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::{
+    audit::{record_audit_event, AuditTarget},
+    auth::check_not_suspended,
+    DbExecutor, PooledConn,
+};
+use crate::app::sessions::{
+    GetSessions, RevokeAllSessions, RevokeSession, SessionListResponse, SessionResponseInner,
+};
+use crate::app::users::{Logout, RefreshSession, UserResponse};
+use crate::models::{NewRefreshToken, NewSession, RefreshToken, Session, User};
+use crate::prelude::*;
+use crate::utils::{
+    auth::ClientInfo,
+    jwt::CanGenerateJwt,
+    token::{generate_token, hash_token},
+    CustomDateTime,
+};
+
+// a session stays alive for this long after it was last refreshed
+pub const SESSION_LIFETIME_DAYS: i64 = 30;
+
+// message handler implementations ↓
+
+impl Message for RefreshSession {
+    type Result = Result<UserResponse>;
+}
+
+impl Handler<RefreshSession> for DbExecutor {
+    type Result = Result<UserResponse>;
+
+    fn handle(&mut self, msg: RefreshSession, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::{refresh_tokens, sessions, users};
+
+        let conn = &self.0.get()?;
+
+        let invalid_token = || {
+            Error::Unauthorized(json!({
+                "error": "Refresh token is invalid",
+            }))
+        };
+
+        let rotated = conn.transaction::<_, Error, _>(|| {
+            // lock the token row so two concurrent refreshes can't both rotate it
+            let refresh_token = refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(hash_token(&msg.refresh_token)))
+                .for_update()
+                .get_result::<RefreshToken>(conn)
+                .optional()?
+                .ok_or_else(invalid_token)?;
+
+            let session = sessions::table
+                .find(refresh_token.session_id)
+                .get_result::<Session>(conn)?;
+
+            let now = Utc::now().naive_utc();
+
+            if session.revoked_at.is_some() || session.expires_at < now {
+                return Ok(None);
+            }
+
+            // A rotated token showing up again means it was stolen by someone,
+            // so the whole family of tokens belonging to the session is revoked
+            if refresh_token.used_at.is_some() {
+                revoke_session(session.id, conn)?;
+                return Ok(None);
+            }
+
+            diesel::update(refresh_tokens::table.find(refresh_token.id))
+                .set(refresh_tokens::used_at.eq(now))
+                .execute(conn)?;
+
+            let session = diesel::update(sessions::table.find(session.id))
+                .set((
+                    sessions::expires_at.eq(now + Duration::days(SESSION_LIFETIME_DAYS)),
+                    sessions::last_seen_at.eq(now),
+                ))
+                .get_result::<Session>(conn)?;
+
+            let new_refresh_token = issue_refresh_token(session.id, conn)?;
+
+            Ok(Some((session, new_refresh_token)))
+        })?;
+
+        let (session, refresh_token) = rotated.ok_or_else(invalid_token)?;
+
+        let user = users::table.find(session.user_id).get_result::<User>(conn)?;
+
+        Ok(UserResponse::new(
+            user,
+            session.generate_jwt()?,
+            Some(refresh_token),
+        ))
+    }
+}
+
+impl Message for Logout {
+    type Result = Result<()>;
+}
+
+impl Handler<Logout> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: Logout, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        match msg.auth.session_id {
+            Some(session_id) => revoke_session(session_id, conn),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Message for GetSessions {
+    type Result = Result<SessionListResponse>;
+}
+
+impl Handler<GetSessions> for DbExecutor {
+    type Result = Result<SessionListResponse>;
+
+    fn handle(&mut self, msg: GetSessions, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::sessions;
+
+        let conn = &self.0.get()?;
+
+        let active_sessions = sessions::table
+            .filter(sessions::user_id.eq(msg.auth.user.id))
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(Utc::now().naive_utc()))
+            .order(sessions::last_seen_at.desc())
+            .load::<Session>(conn)?;
+
+        let session_list = active_sessions
+            .into_iter()
+            .map(|session| SessionResponseInner {
+                current: Some(session.id) == msg.auth.session_id,
+                id: session.id,
+                user_agent: session.user_agent,
+                ip_address: session.ip_address,
+                created_at: CustomDateTime(session.created_at),
+                last_seen_at: CustomDateTime(session.last_seen_at),
+            })
+            .collect();
+
+        Ok(SessionListResponse {
+            sessions: session_list,
+        })
+    }
+}
+
+impl Message for RevokeSession {
+    type Result = Result<()>;
+}
+
+impl Handler<RevokeSession> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: RevokeSession, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::sessions;
+
+        let conn = &self.0.get()?;
+
+        // users can only see and revoke their own sessions
+        let session = sessions::table
+            .filter(sessions::id.eq(msg.session_id))
+            .filter(sessions::user_id.eq(msg.auth.user.id))
+            .get_result::<Session>(conn)?;
+
+        revoke_session(session.id, conn)
+    }
+}
+
+impl Message for RevokeAllSessions {
+    type Result = Result<()>;
+}
+
+impl Handler<RevokeAllSessions> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: RevokeAllSessions, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        revoke_all_sessions(msg.auth.user.id, conn)
+    }
+}
+
+// helper methods ↓
+
+// Starts a new session for a user who just proved who they are, e.g. by logging in
+pub fn start_session(user: User, client: ClientInfo, conn: &PooledConn) -> Result<UserResponse> {
+    use crate::schema::sessions;
+
+    check_not_suspended(&user)?;
+
+    let session = diesel::insert_into(sessions::table)
+        .values(NewSession {
+            user_id: user.id,
+            expires_at: (Utc::now() + Duration::days(SESSION_LIFETIME_DAYS)).naive_utc(),
+            user_agent: client.user_agent.to_owned(),
+            ip_address: client.ip_address.to_owned(),
+        })
+        .get_result::<Session>(conn)?;
+
+    record_audit_event(
+        conn,
+        Some(user.id),
+        &client,
+        "user.login",
+        Some(AuditTarget::User(user.id)),
+        json!({ "sessionId": session.id }),
+    )?;
+
+    let refresh_token = issue_refresh_token(session.id, conn)?;
+
+    Ok(UserResponse::new(
+        user,
+        session.generate_jwt()?,
+        Some(refresh_token),
+    ))
+}
+
+pub fn revoke_session(session_id: Uuid, conn: &PooledConn) -> Result<()> {
+    use crate::schema::sessions;
+
+    diesel::update(sessions::table.find(session_id))
+        .filter(sessions::revoked_at.is_null())
+        .set(sessions::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+// signs a user out everywhere
+pub fn revoke_all_sessions(user_id: Uuid, conn: &PooledConn) -> Result<()> {
+    use crate::schema::sessions;
+
+    diesel::update(sessions::table)
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .set(sessions::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+fn issue_refresh_token(session_id: Uuid, conn: &PooledConn) -> Result<String> {
+    use crate::schema::refresh_tokens;
+
+    let token = generate_token();
+
+    diesel::insert_into(refresh_tokens::table)
+        .values(NewRefreshToken {
+            session_id,
+            token_hash: hash_token(&token),
+        })
+        .execute(conn)?;
+
+    Ok(token)
+}
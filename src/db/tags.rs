@@ -3,7 +3,7 @@ use diesel::prelude::*;
 
 use super::DbExecutor;
 use crate::app::tags::{GetTags, TagsResponse};
-use crate::models::ArticleTag;
+use crate::models::{ArticleStatus, ArticleTag};
 use crate::prelude::*;
 
 impl Message for GetTags {
@@ -15,10 +15,17 @@ impl Handler<GetTags> for DbExecutor {
 
     fn handle(&mut self, _msg: GetTags, _: &mut Self::Context) -> Self::Result {
         use crate::schema::article_tags::dsl::*;
+        use crate::schema::articles;
 
         let conn = &self.0.get()?;
 
+        // tags only used on drafts or unlisted articles would give those away
+        let published_articles = articles::table
+            .filter(articles::status.eq(ArticleStatus::Published.as_str()))
+            .select(articles::id);
+
         let tags = article_tags
+            .filter(article_id.eq_any(published_articles))
             .distinct_on(tag_name)
             .load::<ArticleTag>(conn)?;
 
@@ -0,0 +1,148 @@
+use actix::prelude::*;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::{DbExecutor, PooledConn};
+use crate::app::tokens::{
+    CreateTokenOuter, GetTokens, RevokeToken, TokenListResponse, TokenResponse, TokenResponseInner,
+};
+use crate::models::{NewPersonalAccessToken, PersonalAccessToken};
+use crate::prelude::*;
+use crate::utils::{
+    auth::{Scope, PERSONAL_ACCESS_TOKEN_PREFIX},
+    token::{generate_token, hash_token},
+    CustomDateTime,
+};
+
+// message handler implementations ↓
+
+impl Message for GetTokens {
+    type Result = Result<TokenListResponse>;
+}
+
+impl Handler<GetTokens> for DbExecutor {
+    type Result = Result<TokenListResponse>;
+
+    fn handle(&mut self, msg: GetTokens, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::personal_access_tokens;
+
+        let conn = &self.0.get()?;
+
+        let tokens = personal_access_tokens::table
+            .filter(personal_access_tokens::user_id.eq(msg.auth.user.id))
+            .filter(personal_access_tokens::revoked_at.is_null())
+            .order(personal_access_tokens::created_at.desc())
+            .load::<PersonalAccessToken>(conn)?;
+
+        Ok(TokenListResponse {
+            tokens: tokens
+                .into_iter()
+                .map(|token| TokenResponseInner::new(token, None))
+                .collect(),
+        })
+    }
+}
+
+impl Message for CreateTokenOuter {
+    type Result = Result<TokenResponse>;
+}
+
+impl Handler<CreateTokenOuter> for DbExecutor {
+    type Result = Result<TokenResponse>;
+
+    fn handle(&mut self, msg: CreateTokenOuter, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::personal_access_tokens;
+
+        let conn = &self.0.get()?;
+
+        let invalid_scopes = msg
+            .token
+            .scopes
+            .iter()
+            .any(|scope| Scope::parse(scope).is_none());
+        if msg.token.scopes.is_empty() || invalid_scopes {
+            return Err(Error::UnprocessableEntity(json!({
+                "errors": { "scopes": ["must be one or more of read, articles:write, comments:write, profiles:write, user:write"] },
+            })));
+        }
+
+        let mut scopes = msg.token.scopes;
+        scopes.sort();
+        scopes.dedup();
+
+        let plain_token = format!("{}{}", PERSONAL_ACCESS_TOKEN_PREFIX, generate_token());
+
+        let token = diesel::insert_into(personal_access_tokens::table)
+            .values(NewPersonalAccessToken {
+                user_id: msg.auth.user.id,
+                name: msg.token.name,
+                token_hash: hash_token(&plain_token),
+                scopes,
+                expires_at: msg
+                    .token
+                    .expires_in_days
+                    .map(|days| (Utc::now() + Duration::days(days)).naive_utc()),
+            })
+            .get_result::<PersonalAccessToken>(conn)?;
+
+        Ok(TokenResponse {
+            token: TokenResponseInner::new(token, Some(plain_token)),
+        })
+    }
+}
+
+impl Message for RevokeToken {
+    type Result = Result<()>;
+}
+
+impl Handler<RevokeToken> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: RevokeToken, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::personal_access_tokens;
+
+        let conn = &self.0.get()?;
+
+        // users can only see and revoke their own tokens
+        let token = personal_access_tokens::table
+            .filter(personal_access_tokens::id.eq(msg.id))
+            .filter(personal_access_tokens::user_id.eq(msg.auth.user.id))
+            .filter(personal_access_tokens::revoked_at.is_null())
+            .get_result::<PersonalAccessToken>(conn)?;
+
+        diesel::update(&token)
+            .set(personal_access_tokens::revoked_at.eq(Utc::now().naive_utc()))
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+// helper methods ↓
+
+// For when whoever may have had the password shouldn't keep any way into the account, returns
+// how many tokens were still valid
+pub fn revoke_all_tokens(user_id: Uuid, conn: &PooledConn) -> Result<usize> {
+    use crate::schema::personal_access_tokens;
+
+    Ok(diesel::update(personal_access_tokens::table)
+        .filter(personal_access_tokens::user_id.eq(user_id))
+        .filter(personal_access_tokens::revoked_at.is_null())
+        .set(personal_access_tokens::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(conn)?)
+}
+
+impl TokenResponseInner {
+    fn new(token: PersonalAccessToken, plain_token: Option<String>) -> Self {
+        TokenResponseInner {
+            id: token.id,
+            name: token.name,
+            scopes: token.scopes,
+            token: plain_token,
+            created_at: CustomDateTime(token.created_at),
+            last_used_at: token.last_used_at.map(CustomDateTime),
+            expires_at: token.expires_at.map(CustomDateTime),
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use actix::prelude::*;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::{
+    audit::{record_audit_event, AuditTarget},
+    login_throttles::{
+        check_login_throttle, clear_login_throttle, record_failed_login,
+        second_factor_throttle_keys,
+    },
+    sessions::start_session,
+    DbExecutor, PooledConn,
+};
+use crate::app::users::{
+    ChallengeResponse, ChallengeResponseInner, LoginResponse, LoginTotpOuter, TotpChange,
+    TotpResponse, UserResponse,
+};
+use crate::models::{LoginChallenge, NewLoginChallenge, NewRecoveryCode, RecoveryCode, User};
+use crate::prelude::*;
+use crate::utils::{
+    auth::ClientInfo,
+    jwt::{decode_totp_challenge, generate_totp_challenge, CHALLENGE_LIFETIME_MINUTES},
+    totp::{
+        generate_recovery_codes, generate_secret, hash_recovery_code, matching_time_step,
+        otpauth_uri,
+    },
+};
+
+// wrong codes a single challenge takes before the password has to be entered again
+const CHALLENGE_MAX_FAILED_ATTEMPTS: i32 = 3;
+
+enum ChallengeOutcome {
+    Passed(Box<User>),
+    WrongCode,
+    // already used, expired, or out of attempts
+    Invalid,
+}
+
+// message handler implementations ↓
+
+impl Message for LoginTotpOuter {
+    type Result = Result<UserResponse>;
+}
+
+impl Handler<LoginTotpOuter> for DbExecutor {
+    type Result = Result<UserResponse>;
+
+    fn handle(&mut self, msg: LoginTotpOuter, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::{login_challenges, users};
+
+        let (user_id, challenge_id) = decode_totp_challenge(&msg.login_totp.challenge_token)?;
+
+        let conn = &self.0.get()?;
+
+        let throttle_keys = second_factor_throttle_keys(user_id, &msg.client);
+        check_login_throttle(&throttle_keys, conn)?;
+
+        // the challenge stays locked while its code is checked, so it can't be raced
+        let outcome = conn.transaction::<_, Error, _>(|| {
+            let now = Utc::now().naive_utc();
+
+            let challenge = login_challenges::table
+                .find(challenge_id)
+                .filter(login_challenges::user_id.eq(user_id))
+                .for_update()
+                .get_result::<LoginChallenge>(conn)
+                .optional()?;
+            let challenge = match challenge {
+                Some(challenge)
+                    if challenge.used_at.is_none()
+                        && challenge.expires_at > now
+                        && challenge.failed_attempts < CHALLENGE_MAX_FAILED_ATTEMPTS =>
+                {
+                    challenge
+                }
+                _ => return Ok(ChallengeOutcome::Invalid),
+            };
+
+            let user = users::table.find(user_id).get_result::<User>(conn)?;
+
+            if check_second_factor(&user, &msg.login_totp.code, conn)? {
+                diesel::update(&challenge)
+                    .set(login_challenges::used_at.eq(now))
+                    .execute(conn)?;
+                Ok(ChallengeOutcome::Passed(Box::new(user)))
+            } else {
+                diesel::update(&challenge)
+                    .set(login_challenges::failed_attempts.eq(challenge.failed_attempts + 1))
+                    .execute(conn)?;
+                Ok(ChallengeOutcome::WrongCode)
+            }
+        })?;
+
+        match outcome {
+            ChallengeOutcome::Passed(user) => {
+                clear_login_throttle(&throttle_keys[0], conn)?;
+                start_session(*user, msg.client, conn)
+            }
+            ChallengeOutcome::WrongCode => {
+                record_failed_login(&throttle_keys, conn)?;
+                record_audit_event(
+                    conn,
+                    None,
+                    &msg.client,
+                    "user.login_failed",
+                    Some(AuditTarget::User(user_id)),
+                    json!({ "factor": "totp" }),
+                )?;
+                Err(Error::Unauthorized(json!({
+                    "error": "Wrong code",
+                })))
+            }
+            ChallengeOutcome::Invalid => Err(Error::Unauthorized(json!({
+                "error": "Challenge is invalid or has expired, log in again",
+            }))),
+        }
+    }
+}
+
+// helper methods ↓
+
+// Once the first factor checked out: the session only starts right away for users without a
+// second factor, everyone else gets a challenge for it
+pub fn start_session_or_challenge(
+    user: User,
+    client: ClientInfo,
+    conn: &PooledConn,
+) -> Result<LoginResponse> {
+    if user.totp_enabled_at.is_some() {
+        return Ok(LoginResponse::ChallengeRequired(ChallengeResponse {
+            challenge: ChallengeResponseInner {
+                challenge_type: "totp".into(),
+                token: issue_totp_challenge(user.id, conn)?,
+            },
+        }));
+    }
+
+    Ok(LoginResponse::Authenticated(start_session(user, client, conn)?))
+}
+
+// Hands out the token for the second step of a login, good for one successful code
+fn issue_totp_challenge(user_id: Uuid, conn: &PooledConn) -> Result<String> {
+    use crate::schema::login_challenges;
+
+    let challenge_id = Uuid::new_v4();
+    diesel::insert_into(login_challenges::table)
+        .values(NewLoginChallenge {
+            id: challenge_id,
+            user_id,
+            expires_at: (Utc::now() + Duration::minutes(CHALLENGE_LIFETIME_MINUTES)).naive_utc(),
+        })
+        .execute(conn)?;
+
+    generate_totp_challenge(user_id, challenge_id)
+}
+
+pub fn apply_totp_change(
+    user: User,
+    totp_change: TotpChange,
+    conn: &PooledConn,
+) -> Result<(User, TotpResponse)> {
+    use crate::schema::{recovery_codes, users};
+
+    let invalid_code = || {
+        Error::UnprocessableEntity(json!({
+            "errors": { "totp": ["code is invalid"] },
+        }))
+    };
+
+    match (totp_change.enabled, totp_change.code) {
+        // starting (or restarting) enrollment
+        (true, None) => {
+            if user.totp_enabled_at.is_some() {
+                return Err(Error::UnprocessableEntity(json!({
+                    "errors": { "totp": ["is already enabled"] },
+                })));
+            }
+
+            let secret = generate_secret();
+            let otpauth_uri = otpauth_uri(&secret, &user.username);
+
+            let user = diesel::update(users::table.find(user.id))
+                .set((
+                    users::totp_secret.eq(secret),
+                    // steps seen under an earlier secret say nothing about this one
+                    users::totp_last_used_step.eq(None::<i64>),
+                ))
+                .get_result::<User>(conn)?;
+
+            Ok((
+                user,
+                TotpResponse {
+                    otpauth_uri: Some(otpauth_uri),
+                    recovery_codes: None,
+                },
+            ))
+        }
+        // confirming enrollment with a code from the authenticator
+        (true, Some(code)) => {
+            let secret = match (&user.totp_secret, user.totp_enabled_at) {
+                (Some(secret), None) => secret,
+                _ => {
+                    return Err(Error::UnprocessableEntity(json!({
+                        "errors": { "totp": ["has no enrollment in progress"] },
+                    })))
+                }
+            };
+
+            if !accept_authenticator_code(user.id, secret, &code, conn)? {
+                return Err(invalid_code());
+            }
+
+            let codes = generate_recovery_codes();
+
+            let user = conn.transaction::<_, Error, _>(|| {
+                diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user.id)))
+                    .execute(conn)?;
+
+                let new_recovery_codes = codes
+                    .iter()
+                    .map(|code| NewRecoveryCode {
+                        user_id: user.id,
+                        code_hash: hash_recovery_code(code),
+                    })
+                    .collect::<Vec<NewRecoveryCode>>();
+                diesel::insert_into(recovery_codes::table)
+                    .values(&new_recovery_codes)
+                    .execute(conn)?;
+
+                let user = diesel::update(users::table.find(user.id))
+                    .set(users::totp_enabled_at.eq(Utc::now().naive_utc()))
+                    .get_result::<User>(conn)?;
+
+                Ok(user)
+            })?;
+
+            Ok((
+                user,
+                TotpResponse {
+                    otpauth_uri: None,
+                    recovery_codes: Some(codes),
+                },
+            ))
+        }
+        (false, code) => {
+            // an abandoned enrollment can be dropped without a code
+            if user.totp_enabled_at.is_some() {
+                let code = code.ok_or_else(invalid_code)?;
+                if !check_second_factor(&user, &code, conn)? {
+                    return Err(invalid_code());
+                }
+            }
+
+            let user = conn.transaction::<_, Error, _>(|| {
+                diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user.id)))
+                    .execute(conn)?;
+
+                let user = diesel::update(users::table.find(user.id))
+                    .set((
+                        users::totp_secret.eq(None::<String>),
+                        users::totp_enabled_at.eq(None::<NaiveDateTime>),
+                        users::totp_last_used_step.eq(None::<i64>),
+                    ))
+                    .get_result::<User>(conn)?;
+
+                Ok(user)
+            })?;
+
+            Ok((
+                user,
+                TotpResponse {
+                    otpauth_uri: None,
+                    recovery_codes: None,
+                },
+            ))
+        }
+    }
+}
+
+// Accepts either a code from the authenticator or an unused recovery code, which is used up
+pub fn check_second_factor(user: &User, code: &str, conn: &PooledConn) -> Result<bool> {
+    use crate::schema::recovery_codes;
+
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret,
+        _ => return Ok(false),
+    };
+
+    if accept_authenticator_code(user.id, secret, code, conn)? {
+        return Ok(true);
+    }
+
+    let used_recovery_codes = diesel::update(recovery_codes::table)
+        .filter(recovery_codes::user_id.eq(user.id))
+        .filter(recovery_codes::code_hash.eq(hash_recovery_code(code)))
+        .filter(recovery_codes::used_at.is_null())
+        .set(recovery_codes::used_at.eq(Utc::now().naive_utc()))
+        .get_results::<RecoveryCode>(conn)?;
+
+    Ok(!used_recovery_codes.is_empty())
+}
+
+// local helper methods ↓
+
+// A code is only accepted for a later time step than the last one accepted, so one seen over
+// someone's shoulder or in a log can't be used again while it's still current
+fn accept_authenticator_code(
+    user_id: Uuid,
+    secret: &str,
+    code: &str,
+    conn: &PooledConn,
+) -> Result<bool> {
+    use crate::schema::users;
+
+    let step = match matching_time_step(secret, code)? {
+        Some(step) => step,
+        None => return Ok(false),
+    };
+
+    let accepted = diesel::update(users::table.find(user_id))
+        .filter(
+            users::totp_last_used_step
+                .is_null()
+                .or(users::totp_last_used_step.lt(step)),
+        )
+        .set(users::totp_last_used_step.eq(step))
+        .execute(conn)?;
+
+    Ok(accepted == 1)
+}
@@ -1,79 +1,149 @@
 use actix::prelude::*;
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use libreauth::pass::HashBuilder;
+use uuid::Uuid;
 
-use super::DbExecutor;
-use crate::app::users::{LoginUser, RegisterUser, UpdateUserOuter, UserResponse};
+use super::{
+    audit::{change, record_audit_event, AuditTarget},
+    email_verifications::{create_email_verification, send_email_verification},
+    login_throttles::{
+        check_login_throttle, clear_login_throttle, login_throttle_keys, record_failed_login,
+    },
+    lower,
+    sessions::start_session,
+    totp::{apply_totp_change, start_session_or_challenge},
+    DbExecutor, PooledConn,
+};
+use crate::app::users::{
+    ContentDisposal, DeleteAccountOuter, LoginResponse, LoginUserOuter, RegisterUserOuter,
+    UpdateUserOuter, UserResponse,
+};
 use crate::models::{NewUser, User, UserChange};
 use crate::prelude::*;
-use crate::utils::{HASHER, PWD_SCHEME_VERSION};
+use crate::utils::{
+    password_policy::PASSWORD_POLICY, permissions::Role,
+    token::generate_token, DUMMY_PASSWORD_HASH, HASHER,
+};
 
 // message handler implementations ↓
 
-impl Message for RegisterUser {
+impl Message for RegisterUserOuter {
     type Result = Result<UserResponse>;
 }
 
-impl Handler<RegisterUser> for DbExecutor {
+impl Handler<RegisterUserOuter> for DbExecutor {
     type Result = Result<UserResponse>;
 
-    fn handle(&mut self, msg: RegisterUser, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: RegisterUserOuter, _: &mut Self::Context) -> Self::Result {
         use crate::schema::users::dsl::*;
 
+        let register_user = msg.register_user;
+        let client = msg.client;
+
+        PASSWORD_POLICY.check(
+            &register_user.password,
+            &register_user.username,
+            &register_user.email,
+        )?;
+
         let new_user = NewUser {
-            username: msg.username.clone(),
-            email: msg.email.clone(),
-            password: HASHER.hash(&msg.password)?,
+            username: register_user.username,
+            email: register_user.email,
+            password: HASHER.hash(&register_user.password)?,
             bio: None,
             image: None,
         };
 
         let conn = &self.0.get()?;
 
-        match diesel::insert_into(users)
-            .values(new_user)
-            .get_result::<User>(conn)
-        {
-            Ok(user) => Ok(user.into()),
-            Err(e) => Err(e.into()),
-        }
+        // a failure past the insert mustn't leave an account behind that blocks signing up again
+        let (response, mail) = conn.transaction::<_, Error, _>(|| {
+            let user = diesel::insert_into(users)
+                .values(new_user)
+                .get_result::<User>(conn)?;
+
+            let mail = create_email_verification(&user, &user.email, conn)?;
+
+            Ok((start_session(user, client, conn)?, mail))
+        })?;
+
+        send_email_verification(&mail);
+
+        Ok(response)
     }
 }
 
-impl Message for LoginUser {
-    type Result = Result<UserResponse>;
+impl Message for LoginUserOuter {
+    type Result = Result<LoginResponse>;
 }
 
-impl Handler<LoginUser> for DbExecutor {
-    type Result = Result<UserResponse>;
+impl Handler<LoginUserOuter> for DbExecutor {
+    type Result = Result<LoginResponse>;
 
-    fn handle(&mut self, msg: LoginUser, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: LoginUserOuter, _: &mut Self::Context) -> Self::Result {
         use crate::schema::users::dsl::*;
 
-        let provided_password_raw = &msg.password;
+        let login_user = msg.login_user;
+        let provided_password_raw = &login_user.password;
 
         let conn = &self.0.get()?;
 
-        let stored_user: User = users.filter(email.eq(msg.email)).first(conn)?;
-        let checker = HashBuilder::from_phc(&stored_user.password)?;
-
-        if checker.is_valid(provided_password_raw) {
-            if checker.needs_update(PWD_SCHEME_VERSION) {
-                let new_password = HASHER.hash(provided_password_raw)?;
-                return match diesel::update(users.find(stored_user.id))
-                    .set(password.eq(new_password))
-                    .get_result::<User>(conn)
-                {
-                    Ok(user) => Ok(user.into()),
-                    Err(e) => Err(e.into()),
-                };
-            }
-            Ok(stored_user.into())
+        // usernames can't contain an @, so that's enough to tell which one was typed in
+        let login = login_user.login.trim().to_lowercase();
+        let stored_user = if login.contains('@') {
+            users.filter(lower(email).eq(&login)).first::<User>(conn)
         } else {
-            Err(Error::Unauthorized(json!({
-                "error": "Wrong password",
-            })))
+            users.filter(lower(username).eq(&login)).first::<User>(conn)
         }
+        .optional()?;
+
+        // failures count against the account whether it was named by its username or its email
+        let throttle_account = match stored_user {
+            Some(ref stored_user) => stored_user.id.to_string(),
+            None => login,
+        };
+        let throttle_keys = login_throttle_keys(&throttle_account, &msg.client);
+        check_login_throttle(&throttle_keys, conn)?;
+
+        // an unknown login still costs a hash so it can't be told apart by timing
+        let stored_hash = match stored_user {
+            Some(ref stored_user) => stored_user.password.as_str(),
+            None => DUMMY_PASSWORD_HASH.as_str(),
+        };
+        let password_matches = HASHER.verify(stored_hash, provided_password_raw)?;
+
+        let stored_user = match stored_user {
+            Some(stored_user) if password_matches => stored_user,
+            stored_user => {
+                record_failed_login(&throttle_keys, conn)?;
+                record_audit_event(
+                    conn,
+                    None,
+                    &msg.client,
+                    "user.login_failed",
+                    stored_user.map(|stored_user| AuditTarget::User(stored_user.id)),
+                    json!({ "login": login_user.login }),
+                )?;
+                // the same answer whether or not the login is known
+                return Err(Error::Unauthorized(json!({
+                    "error": "Login or password is invalid",
+                })));
+            }
+        };
+
+        clear_login_throttle(&throttle_keys[0], conn)?;
+
+        // the password is only ever known right now, so this is when old hashes get replaced
+        let user = if HASHER.needs_rehash(&stored_user.password) {
+            let new_password = HASHER.hash(provided_password_raw)?;
+            diesel::update(users.find(stored_user.id))
+                .set(password.eq(new_password))
+                .get_result::<User>(conn)?
+        } else {
+            stored_user
+        };
+
+        start_session_or_challenge(user, msg.client, conn)
     }
 }
 
@@ -92,25 +162,272 @@ impl Handler<UpdateUserOuter> for DbExecutor {
 
         let conn = &self.0.get()?;
 
+        let previous_username = auth.user.username.to_owned();
+        let previous_email = auth.user.email.to_owned();
+        let totp_was_enabled = auth.user.totp_enabled_at.is_some();
+
         let updated_password = match update_user.password {
-            Some(updated_password) => Some(HASHER.hash(&updated_password)?),
+            Some(ref updated_password) => {
+                // checked against the username and email the account is about to have
+                let new_username = update_user.username.as_ref().unwrap_or(&auth.user.username);
+                let new_email = update_user.email.as_ref().unwrap_or(&auth.user.email);
+                PASSWORD_POLICY.check(updated_password, new_username, new_email)?;
+                Some(HASHER.hash(updated_password)?)
+            }
             None => None,
         };
 
+        // a new email only replaces the current one once it has been verified
+        let pending_email = match update_user.email {
+            Some(new_email) if new_email != auth.user.email => {
+                let taken = users
+                    .filter(lower(email).eq(new_email.to_lowercase()))
+                    .filter(id.ne(auth.user.id))
+                    .first::<User>(conn)
+                    .optional()?
+                    .is_some();
+                if taken {
+                    return Err(Error::UnprocessableEntity(json!({
+                        "errors": { "email": ["has already been taken"] },
+                    })));
+                }
+                Some(new_email)
+            }
+            _ => None,
+        };
+
         let updated_user = UserChange {
             username: update_user.username,
-            email: update_user.email,
             password: updated_password,
             bio: update_user.bio,
             image: update_user.image,
         };
 
-        match diesel::update(users.find(auth.user.id))
-            .set(&updated_user)
-            .get_result::<User>(conn)
-        {
-            Ok(user) => Ok(user.into()),
-            Err(e) => Err(e.into()),
+        let current_user = auth.user;
+        let totp_change = update_user.totp;
+        let client = &auth.client;
+
+        // a wrong code for the second factor must not leave the rest of the changes behind
+        let (user, totp, mail) = conn.transaction::<_, Error, _>(|| {
+            let (user, totp) = match totp_change {
+                Some(totp_change) => {
+                    let (user, totp) = apply_totp_change(current_user, totp_change, conn)?;
+                    (user, Some(totp))
+                }
+                None => (current_user, None),
+            };
+
+            // diesel refuses to run an update without any changes, e.g. when only the email was changed
+            let user = if updated_user.is_empty() {
+                user
+            } else {
+                diesel::update(users.find(user.id))
+                    .set(&updated_user)
+                    .get_result::<User>(conn)?
+            };
+
+            let mail = match pending_email {
+                Some(ref new_email) => Some(create_email_verification(&user, new_email, conn)?),
+                None => None,
+            };
+
+            let target = || Some(AuditTarget::User(user.id));
+            let audit = |action, changes| {
+                record_audit_event(conn, Some(user.id), client, action, target(), changes)
+            };
+            if updated_user.password.is_some() {
+                audit("user.password_changed", json!({ "via": "settings" }))?;
+            }
+            if user.username != previous_username {
+                audit(
+                    "user.username_changed",
+                    json!({ "username": change(&previous_username, &user.username) }),
+                )?;
+            }
+            if let Some(ref new_email) = pending_email {
+                audit(
+                    "user.email_change_requested",
+                    json!({ "email": change(&previous_email, new_email) }),
+                )?;
+            }
+            if user.totp_enabled_at.is_some() != totp_was_enabled {
+                audit(
+                    "user.two_factor_changed",
+                    json!({ "enabled": change(totp_was_enabled, !totp_was_enabled) }),
+                )?;
+            }
+
+            Ok((user, totp, mail))
+        })?;
+
+        if let Some(ref mail) = mail {
+            send_email_verification(mail);
         }
+
+        let mut response = UserResponse::new(user, auth.token, None);
+        response.user.pending_email = pending_email;
+        response.user.totp = totp;
+        Ok(response)
     }
 }
+
+impl Message for DeleteAccountOuter {
+    type Result = Result<()>;
+}
+
+impl Handler<DeleteAccountOuter> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: DeleteAccountOuter, _: &mut Self::Context) -> Self::Result {
+        let user = msg.auth.user;
+        let delete_account = msg.delete_account;
+
+        let conn = &self.0.get()?;
+
+        // the same counters as logging in, a stolen session mustn't be a way to guess the password
+        let throttle_keys = login_throttle_keys(&user.id.to_string(), &msg.auth.client);
+        check_login_throttle(&throttle_keys, conn)?;
+
+        if !HASHER.verify(&user.password, &delete_account.password)? {
+            record_failed_login(&throttle_keys, conn)?;
+            return Err(Error::UnprocessableEntity(json!({
+                "errors": { "password": ["is incorrect"] },
+            })));
+        }
+
+        let content = match delete_account.content {
+            None | Some(ContentDisposal::Delete) => {
+                delete_user(user.id, conn)?;
+                "deleted"
+            }
+            Some(ContentDisposal::Anonymize) => {
+                anonymize_user(user.id, conn)?;
+                "anonymized"
+            }
+        };
+
+        record_audit_event(
+            conn,
+            Some(user.id),
+            &msg.auth.client,
+            "user.deleted",
+            Some(AuditTarget::User(user.id)),
+            json!({ "username": change(Some(&user.username), None), "content": content }),
+        )
+    }
+}
+
+// helper methods ↓
+
+// Removes a user together with everything that belongs to them, including their articles and
+// whatever other users attached to those
+pub fn delete_user(user_id: Uuid, conn: &PooledConn) -> Result<()> {
+    use crate::schema::{
+        article_revisions, article_tags, articles, comments, favorite_articles, users,
+    };
+
+    conn.transaction::<_, Error, _>(|| {
+        remove_account_data(user_id, conn)?;
+
+        let own_articles = articles::table
+            .filter(articles::author_id.eq(user_id))
+            .select(articles::id);
+
+        diesel::delete(article_tags::table.filter(article_tags::article_id.eq_any(own_articles)))
+            .execute(conn)?;
+        diesel::delete(article_revisions::table.filter(article_revisions::article_id.eq_any(own_articles)))
+            .execute(conn)?;
+        diesel::delete(favorite_articles::table.filter(favorite_articles::article_id.eq_any(own_articles)))
+            .execute(conn)?;
+        diesel::delete(
+            comments::table.filter(
+                comments::article_id
+                    .eq_any(own_articles)
+                    .or(comments::user_id.eq(user_id)),
+            ),
+        )
+        .execute(conn)?;
+        diesel::delete(articles::table.filter(articles::author_id.eq(user_id))).execute(conn)?;
+
+        diesel::delete(users::table.find(user_id)).execute(conn)?;
+
+        Ok(())
+    })
+}
+
+// Keeps a user's articles and comments around, but nothing that says who wrote them
+pub fn anonymize_user(user_id: Uuid, conn: &PooledConn) -> Result<()> {
+    use crate::schema::users;
+
+    // the id is random, so this neither collides nor points back to the old account
+    let anonymous_name = format!("deleted_{}", &Uuid::new_v4().to_simple().to_string()[..12]);
+    // a random password nobody knows, so the account can't be signed into again
+    let unusable_password = HASHER.hash(&generate_token())?;
+
+    conn.transaction::<_, Error, _>(|| {
+        remove_account_data(user_id, conn)?;
+
+        diesel::update(users::table.find(user_id))
+            .set((
+                users::username.eq(&anonymous_name),
+                users::email.eq(format!("{}@deleted.invalid", anonymous_name)),
+                users::password.eq(unusable_password),
+                users::bio.eq(None::<String>),
+                users::image.eq(None::<String>),
+                users::email_verified_at.eq(None::<NaiveDateTime>),
+                users::totp_secret.eq(None::<String>),
+                users::totp_enabled_at.eq(None::<NaiveDateTime>),
+                users::totp_last_used_step.eq(None::<i64>),
+                users::role.eq(Role::User.as_str()),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    })
+}
+
+// Everything tied to a user that isn't content: how they sign in, who they follow and what they
+// favorited
+fn remove_account_data(user_id: Uuid, conn: &PooledConn) -> Result<()> {
+    use crate::schema::{
+        email_verifications, favorite_articles, followers, identities, login_challenges,
+        oidc_pending_links, password_reset_tokens, personal_access_tokens, recovery_codes,
+        refresh_tokens, sessions,
+    };
+
+    diesel::delete(favorite_articles::table.filter(favorite_articles::user_id.eq(user_id)))
+        .execute(conn)?;
+    diesel::delete(
+        followers::table.filter(
+            followers::user_id
+                .eq(user_id)
+                .or(followers::follower_id.eq(user_id)),
+        ),
+    )
+    .execute(conn)?;
+
+    let own_sessions = sessions::table
+        .filter(sessions::user_id.eq(user_id))
+        .select(sessions::id);
+    diesel::delete(refresh_tokens::table.filter(refresh_tokens::session_id.eq_any(own_sessions)))
+        .execute(conn)?;
+    diesel::delete(sessions::table.filter(sessions::user_id.eq(user_id))).execute(conn)?;
+
+    diesel::delete(password_reset_tokens::table.filter(password_reset_tokens::user_id.eq(user_id)))
+        .execute(conn)?;
+    diesel::delete(email_verifications::table.filter(email_verifications::user_id.eq(user_id)))
+        .execute(conn)?;
+    diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+        .execute(conn)?;
+    diesel::delete(login_challenges::table.filter(login_challenges::user_id.eq(user_id)))
+        .execute(conn)?;
+    diesel::delete(
+        personal_access_tokens::table.filter(personal_access_tokens::user_id.eq(user_id)),
+    )
+    .execute(conn)?;
+    diesel::delete(identities::table.filter(identities::user_id.eq(user_id))).execute(conn)?;
+    diesel::delete(oidc_pending_links::table.filter(oidc_pending_links::user_id.eq(user_id)))
+        .execute(conn)?;
+
+    Ok(())
+}
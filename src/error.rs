@@ -1,13 +1,17 @@
-use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
+use actix_web::{
+    error::ResponseError,
+    http::{header::RETRY_AFTER, StatusCode},
+    HttpResponse,
+};
 use actix::MailboxError;
 use diesel::{
     r2d2::PoolError,
     result::{DatabaseErrorKind, Error as DieselError},
 };
 use jwt::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
-use libreauth::pass::ErrorCode as PassErrorCode;
+use libreauth::{oath::ErrorCode as OathErrorCode, pass::ErrorCode as PassErrorCode};
 use serde_json::{Map as JsonMap, Value as JsonValue};
-use std::convert::From;
+use std::{convert::From, io};
 use validator::ValidationErrors;
 
 #[derive(Fail, Debug)]
@@ -24,10 +28,22 @@ pub enum Error {
     #[fail(display = "Not Found: {}", _0)]
     NotFound(JsonValue),
 
+    // 412
+    #[fail(display = "Precondition Failed: {}", _0)]
+    PreconditionFailed(JsonValue),
+
     // 422
     #[fail(display = "Unprocessable Entity: {}", _0)]
     UnprocessableEntity(JsonValue),
 
+    // 429
+    #[fail(display = "Too Many Requests: {}", message)]
+    TooManyRequests {
+        message: JsonValue,
+        // seconds until the client may try again
+        retry_after: i64,
+    },
+
     // 500
     #[fail(display = "Internal Server Error")]
     InternalServerError,
@@ -41,9 +57,16 @@ impl ResponseError for Error {
             Error::Unauthorized(ref message) => HttpResponse::Unauthorized().json(message),
             Error::Forbidden(ref message) => HttpResponse::Forbidden().json(message),
             Error::NotFound(ref message) => HttpResponse::NotFound().json(message),
+            Error::PreconditionFailed(ref message) => HttpResponse::PreconditionFailed().json(message),
             Error::UnprocessableEntity(ref message) => {
                 HttpResponse::build(StatusCode::UNPROCESSABLE_ENTITY).json(message)
             }
+            Error::TooManyRequests {
+                ref message,
+                retry_after,
+            } => HttpResponse::TooManyRequests()
+                .header(RETRY_AFTER, retry_after.to_string())
+                .json(message),
             Error::InternalServerError => {
                 HttpResponse::InternalServerError().json("Internal Server Error")
             }
@@ -66,6 +89,12 @@ impl From<JwtError> for Error {
             JwtErrorKind::InvalidIssuer => Error::Unauthorized(json!({
                 "error": "Issuer is invalid",
             })),
+            JwtErrorKind::InvalidAudience => Error::Unauthorized(json!({
+                "error": "Audience is invalid",
+            })),
+            JwtErrorKind::ExpiredSignature => Error::Unauthorized(json!({
+                "error": "Token has expired",
+            })),
             _ => Error::Unauthorized(json!({
                 "error": "An issue was found with the token provided",
             })),
@@ -78,6 +107,12 @@ impl From<DieselError> for Error {
         match error {
             DieselError::DatabaseError(kind, info) => {
                 if let DatabaseErrorKind::UniqueViolation = kind {
+                    // a clash on a field the client chose gets the same shape as a validation error
+                    if let Some(field) = info.constraint_name().and_then(unique_field) {
+                        return Error::UnprocessableEntity(json!({
+                            "errors": { field: ["has already been taken"] },
+                        }));
+                    }
                     let message = info.details().unwrap_or_else(|| info.message()).to_string();
                     return Error::UnprocessableEntity(json!({ "error": message }));
                 }
@@ -91,6 +126,21 @@ impl From<DieselError> for Error {
     }
 }
 
+// The field behind each unique constraint or index that user input can run into
+fn unique_field(constraint: &str) -> Option<&'static str> {
+    match constraint {
+        "users_username_key" => Some("username"),
+        "users_email_key" => Some("email"),
+        _ => None,
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(_error: io::Error) -> Self {
+        Error::InternalServerError
+    }
+}
+
 impl From<PoolError> for Error {
     fn from(_error: PoolError) -> Self {
         Error::InternalServerError
@@ -103,6 +153,12 @@ impl From<PassErrorCode> for Error {
     }
 }
 
+impl From<OathErrorCode> for Error {
+    fn from(_error: OathErrorCode) -> Self {
+        Error::InternalServerError
+    }
+}
+
 impl From<ValidationErrors> for Error {
     fn from(errors: ValidationErrors) -> Self {
         let mut err_map = JsonMap::new();
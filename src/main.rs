@@ -13,6 +13,7 @@ extern crate serde_json;
 extern crate validator_derive;
 
 mod app;
+mod cli;
 mod db;
 mod error;
 mod models;
@@ -30,9 +31,18 @@ fn main() {
     }
     env_logger::init();
 
-    let sys = actix::System::new("conduit");
+    match env::args().nth(1).as_deref() {
+        Some("hash-report") => cli::run(cli::hash_report),
+        Some(command) => {
+            eprintln!("Unknown command {}, the only one is hash-report", command);
+            std::process::exit(2);
+        }
+        None => {
+            let sys = actix::System::new("conduit");
 
-    app::start();
+            app::start();
 
-    let _ = sys.run();
+            let _ = sys.run();
+        }
+    }
 }
@@ -3,7 +3,8 @@ use uuid::Uuid;
 
 use crate::schema::{articles, favorite_articles};
 
-#[derive(Debug, Queryable, Identifiable)]
+#[derive(Debug, Queryable, QueryableByName, Identifiable)]
+#[table_name = "articles"]
 pub struct Article {
     pub id: Uuid,
     pub author_id: Uuid,
@@ -13,6 +14,50 @@ pub struct Article {
     pub body: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub status: String,
+    pub published_at: Option<NaiveDateTime>,
+    // when the scheduler is to publish the article
+    pub publish_at: Option<NaiveDateTime>,
+    // goes up by one with every change, see the ETag of article responses
+    pub version: i32,
+    // body rendered from Markdown, None for articles from before it was stored
+    pub body_html: Option<String>,
+}
+
+impl Article {
+    pub fn status(&self) -> ArticleStatus {
+        // anything unknown stays with its author
+        ArticleStatus::parse(&self.status).unwrap_or(ArticleStatus::Draft)
+    }
+}
+
+// Drafts are only seen by their author, unlisted articles by anyone who has the link, and only
+// published ones show up in listings and feeds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArticleStatus {
+    Draft,
+    Published,
+    Unlisted,
+}
+
+impl ArticleStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ArticleStatus::Draft => "draft",
+            ArticleStatus::Published => "published",
+            ArticleStatus::Unlisted => "unlisted",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<ArticleStatus> {
+        match status {
+            "draft" => Some(ArticleStatus::Draft),
+            "published" => Some(ArticleStatus::Published),
+            "unlisted" => Some(ArticleStatus::Unlisted),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Insertable)]
@@ -24,6 +69,10 @@ pub struct NewArticle {
     pub title: String,
     pub description: String,
     pub body: String,
+    pub status: String,
+    pub published_at: Option<NaiveDateTime>,
+    pub publish_at: Option<NaiveDateTime>,
+    pub body_html: String,
 }
 
 #[derive(Debug, AsChangeset)]
@@ -33,6 +82,13 @@ pub struct ArticleChange {
     pub title: Option<String>,
     pub description: Option<String>,
     pub body: Option<String>,
+    // set together with body
+    pub body_html: Option<String>,
+    pub status: Option<String>,
+    // only ever set, the first publication date is kept when an article is unpublished
+    pub published_at: Option<NaiveDateTime>,
+    // None leaves the schedule alone, Some(None) cancels it
+    pub publish_at: Option<Option<NaiveDateTime>>,
 }
 
 #[derive(Debug, Insertable)]
@@ -0,0 +1,24 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::schema::article_revisions;
+
+#[derive(Debug, Queryable)]
+pub struct ArticleRevision {
+    pub article_id: Uuid,
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "article_revisions"]
+pub struct NewArticleRevision {
+    pub article_id: Uuid,
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+}
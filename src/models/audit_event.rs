@@ -0,0 +1,30 @@
+use chrono::NaiveDateTime;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::schema::audit_events;
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub changes: JsonValue,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "audit_events"]
+pub struct NewAuditEvent {
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub changes: JsonValue,
+}
@@ -11,6 +11,8 @@ pub struct Comment {
     pub body: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    // body rendered from Markdown, None for comments from before it was stored
+    pub body_html: Option<String>,
 }
 
 #[derive(Debug, Insertable)]
@@ -19,4 +21,5 @@ pub struct NewComment {
     pub article_id: Uuid,
     pub user_id: Uuid,
     pub body: String,
+    pub body_html: String,
 }
@@ -0,0 +1,25 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::schema::email_verifications;
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct EmailVerification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "email_verifications"]
+pub struct NewEmailVerification {
+    pub user_id: Uuid,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
@@ -0,0 +1,74 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::schema::{identities, oidc_login_states, oidc_pending_links};
+
+#[derive(Debug, Queryable, Identifiable)]
+#[table_name = "identities"]
+pub struct Identity {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "identities"]
+pub struct NewIdentity {
+    pub user_id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct OidcLoginState {
+    pub id: Uuid,
+    pub provider: String,
+    pub state_hash: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub binding_hash: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "oidc_login_states"]
+pub struct NewOidcLoginState {
+    pub provider: String,
+    pub state_hash: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub expires_at: NaiveDateTime,
+    pub binding_hash: String,
+}
+
+#[derive(Debug, Queryable, Identifiable)]
+#[table_name = "oidc_pending_links"]
+pub struct OidcPendingLink {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "oidc_pending_links"]
+pub struct NewOidcPendingLink {
+    pub user_id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
@@ -0,0 +1,23 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::schema::login_challenges;
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct LoginChallenge {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub failed_attempts: i32,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "login_challenges"]
+pub struct NewLoginChallenge {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: NaiveDateTime,
+}
@@ -0,0 +1,20 @@
+use chrono::NaiveDateTime;
+
+use crate::schema::login_throttles;
+
+#[derive(Debug, Queryable, Identifiable)]
+#[primary_key(key)]
+pub struct LoginThrottle {
+    pub key: String,
+    pub failed_attempts: i32,
+    pub last_failed_at: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "login_throttles"]
+pub struct NewLoginThrottle<'a> {
+    pub key: &'a str,
+}
@@ -1,7 +1,21 @@
 mod article;
+mod article_revision;
 mod article_tag;
+mod audit_event;
 mod comment;
+mod email_verification;
 mod follower;
+mod identity;
+mod login_challenge;
+mod login_throttle;
+mod password_reset_token;
+mod personal_access_token;
+mod recovery_code;
+mod session;
 mod user;
 
-pub use self::{article::*, article_tag::*, comment::*, follower::*, user::*};
+pub use self::{
+    article::*, article_revision::*, article_tag::*, audit_event::*, comment::*,
+    email_verification::*, follower::*, identity::*, login_challenge::*, login_throttle::*, password_reset_token::*,
+    personal_access_token::*, recovery_code::*, session::*, user::*,
+};
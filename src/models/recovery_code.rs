@@ -0,0 +1,21 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::schema::recovery_codes;
+
+#[derive(Debug, Queryable, Identifiable)]
+pub struct RecoveryCode {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub code_hash: String,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "recovery_codes"]
+pub struct NewRecoveryCode {
+    pub user_id: Uuid,
+    pub code_hash: String,
+}
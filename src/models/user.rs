@@ -2,6 +2,7 @@ use chrono::NaiveDateTime;
 use uuid::Uuid;
 
 use crate::schema::users;
+use crate::utils::permissions::Role;
 
 #[derive(Debug, Queryable, Identifiable)]
 pub struct User {
@@ -13,6 +14,20 @@ pub struct User {
     pub image: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<NaiveDateTime>,
+    pub role: String,
+    pub suspended_at: Option<NaiveDateTime>,
+    // time step of the last authenticator code that was accepted, so it can't be used again
+    pub totp_last_used_step: Option<i64>,
+}
+
+impl User {
+    pub fn role(&self) -> Role {
+        // anything unknown gets the least privileges
+        Role::parse(&self.role).unwrap_or(Role::User)
+    }
 }
 
 #[derive(Debug, Insertable)]
@@ -29,8 +44,16 @@ pub struct NewUser {
 #[table_name = "users"]
 pub struct UserChange {
     pub username: Option<String>,
-    pub email: Option<String>,
     pub password: Option<String>,
     pub bio: Option<String>,
     pub image: Option<String>,
 }
+
+impl UserChange {
+    pub fn is_empty(&self) -> bool {
+        self.username.is_none()
+            && self.password.is_none()
+            && self.bio.is_none()
+            && self.image.is_none()
+    }
+}
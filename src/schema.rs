@@ -8,6 +8,22 @@ table! {
         body -> Text,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        status -> Text,
+        published_at -> Nullable<Timestamp>,
+        publish_at -> Nullable<Timestamp>,
+        version -> Int4,
+        body_html -> Nullable<Text>,
+    }
+}
+
+table! {
+    article_revisions (article_id, number) {
+        article_id -> Uuid,
+        number -> Int4,
+        title -> Text,
+        description -> Text,
+        body -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -20,6 +36,20 @@ table! {
     }
 }
 
+table! {
+    audit_events (id) {
+        id -> Int8,
+        actor_id -> Nullable<Uuid>,
+        action -> Text,
+        target_type -> Nullable<Text>,
+        target_id -> Nullable<Text>,
+        ip_address -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        changes -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     comments (id) {
         id -> Int4,
@@ -28,6 +58,20 @@ table! {
         body -> Text,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        body_html -> Nullable<Text>,
+    }
+}
+
+table! {
+    email_verifications (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        email -> Varchar,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -49,6 +93,132 @@ table! {
     }
 }
 
+table! {
+    identities (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        provider -> Text,
+        subject -> Text,
+        email -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    login_challenges (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        failed_attempts -> Int4,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    login_throttles (key) {
+        key -> Text,
+        failed_attempts -> Int4,
+        last_failed_at -> Timestamp,
+        locked_until -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    oidc_login_states (id) {
+        id -> Uuid,
+        provider -> Text,
+        state_hash -> Text,
+        nonce -> Text,
+        code_verifier -> Text,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        binding_hash -> Text,
+    }
+}
+
+table! {
+    oidc_pending_links (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        provider -> Text,
+        subject -> Text,
+        email -> Nullable<Text>,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    password_reset_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    personal_access_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Text,
+        token_hash -> Text,
+        scopes -> Array<Text>,
+        last_used_at -> Nullable<Timestamp>,
+        expires_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        session_id -> Uuid,
+        token_hash -> Text,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        user_agent -> Nullable<Text>,
+        ip_address -> Nullable<Text>,
+        last_seen_at -> Timestamp,
+    }
+}
+
 table! {
     users (id) {
         id -> Uuid,
@@ -59,21 +229,50 @@ table! {
         image -> Nullable<Text>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        email_verified_at -> Nullable<Timestamp>,
+        totp_secret -> Nullable<Text>,
+        totp_enabled_at -> Nullable<Timestamp>,
+        role -> Text,
+        suspended_at -> Nullable<Timestamp>,
+        totp_last_used_step -> Nullable<Int8>,
     }
 }
 
+joinable!(article_revisions -> articles (article_id));
 joinable!(article_tags -> articles (article_id));
 joinable!(articles -> users (author_id));
 joinable!(comments -> articles (article_id));
 joinable!(comments -> users (user_id));
+joinable!(email_verifications -> users (user_id));
 joinable!(favorite_articles -> articles (article_id));
 joinable!(favorite_articles -> users (user_id));
+joinable!(identities -> users (user_id));
+joinable!(login_challenges -> users (user_id));
+joinable!(oidc_pending_links -> users (user_id));
+joinable!(password_reset_tokens -> users (user_id));
+joinable!(personal_access_tokens -> users (user_id));
+joinable!(recovery_codes -> users (user_id));
+joinable!(refresh_tokens -> sessions (session_id));
+joinable!(sessions -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
     articles,
+    article_revisions,
     article_tags,
+    audit_events,
     comments,
+    email_verifications,
     favorite_articles,
     followers,
+    identities,
+    login_challenges,
+    login_throttles,
+    oidc_login_states,
+    oidc_pending_links,
+    password_reset_tokens,
+    personal_access_tokens,
+    recovery_codes,
+    refresh_tokens,
+    sessions,
     users,
 );
@@ -1,35 +1,152 @@
-use actix_web::{http::header::AUTHORIZATION, HttpRequest, web::Data};
+use actix_web::{
+    http::header::{AUTHORIZATION, USER_AGENT},
+    HttpRequest,
+    web::Data,
+};
 use futures::{future::result, Future};
 use http::header::HeaderValue;
+use uuid::Uuid;
 
 use crate::app::AppState;
 use crate::models::User;
 use crate::prelude::*;
+use crate::utils::proxies::TRUSTED_PROXIES;
 
 const TOKEN_PREFIX: &str = "Token ";
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+// personal access tokens are sent the same way as JWTs, this tells them apart
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "cpat_";
 
 // expand this as needed
 #[derive(Debug)]
 pub struct Auth {
     pub user: User,
     pub token: String,
+    // only set when authenticated through a session, i.e. not with a personal access token
+    pub session_id: Option<Uuid>,
+    // None means full access
+    pub scopes: Option<Vec<Scope>>,
+    // where the request came from, for the audit log
+    pub client: ClientInfo,
+}
+
+impl Auth {
+    pub fn require_scope(self, scope: Scope) -> Result<Auth> {
+        let allowed = match self.scopes {
+            None => true,
+            // any token may read what its user can read
+            Some(_) if scope == Scope::Read => true,
+            Some(ref scopes) => scopes.contains(&scope),
+        };
+
+        if !allowed {
+            return Err(Error::Forbidden(json!({
+                "error": format!("token is missing the {} scope", scope.as_str()),
+            })));
+        }
+        Ok(self)
+    }
+
+    // For managing the account itself, which personal access tokens can't do
+    pub fn require_session(self) -> Result<Auth> {
+        if self.session_id.is_none() {
+            return Err(Error::Forbidden(json!({
+                "error": "this requires signing in, a personal access token can't be used",
+            })));
+        }
+        Ok(self)
+    }
+}
+
+// What a personal access token can be allowed to do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Read,
+    ArticlesWrite,
+    CommentsWrite,
+    ProfilesWrite,
+    UserWrite,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::ArticlesWrite => "articles:write",
+            Scope::CommentsWrite => "comments:write",
+            Scope::ProfilesWrite => "profiles:write",
+            Scope::UserWrite => "user:write",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Scope> {
+        match scope {
+            "read" => Some(Scope::Read),
+            "articles:write" => Some(Scope::ArticlesWrite),
+            "comments:write" => Some(Scope::CommentsWrite),
+            "profiles:write" => Some(Scope::ProfilesWrite),
+            "user:write" => Some(Scope::UserWrite),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Credentials {
+    Jwt(String),
+    PersonalAccessToken(String),
 }
 
 // create auth message
 #[derive(Debug)]
 pub struct GenerateAuth {
-    pub token: String,
+    pub credentials: Credentials,
+    pub client: ClientInfo,
+}
+
+// where a request came from, recorded against sessions
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        // connection_info().remote() would take X-Forwarded-For from anyone, so the header is
+        // only listened to when the request came through one of the trusted proxies
+        let ip_address = req.peer_addr().map(|peer| {
+            let forwarded_for = req
+                .headers()
+                .get(X_FORWARDED_FOR)
+                .and_then(|forwarded_for| forwarded_for.to_str().ok());
+            TRUSTED_PROXIES
+                .client_addr(peer.ip(), forwarded_for)
+                .to_string()
+        });
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .map(|user_agent| user_agent.to_owned());
+
+        ClientInfo {
+            ip_address,
+            user_agent,
+        }
+    }
 }
 
 pub fn authenticate(state: &Data<AppState>, req: &HttpRequest) -> impl Future<Item = Auth, Error = Error> {
     let db = state.db.clone();
+    let client = ClientInfo::from_request(req);
 
     result(preprocess_authz_token(req.headers().get(AUTHORIZATION)))
-        .and_then(move |token| db.send(GenerateAuth { token }).from_err())
+        .and_then(move |credentials| db.send(GenerateAuth { credentials, client }).from_err())
         .flatten()
 }
 
-fn preprocess_authz_token(token: Option<&HeaderValue>) -> Result<String> {
+fn preprocess_authz_token(token: Option<&HeaderValue>) -> Result<Credentials> {
     let token = match token {
         Some(token) => token.to_str().unwrap(),
         None => {
@@ -47,5 +164,9 @@ fn preprocess_authz_token(token: Option<&HeaderValue>) -> Result<String> {
 
     let token = token.replacen(TOKEN_PREFIX, "", 1);
 
-    Ok(token)
+    if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+        return Ok(Credentials::PersonalAccessToken(token));
+    }
+
+    Ok(Credentials::Jwt(token))
 }
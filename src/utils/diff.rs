@@ -0,0 +1,85 @@
+// above this many lines times lines the table for the longest common subsequence gets too big,
+// and the whole text is shown as replaced instead
+const MAX_TABLE_SIZE: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+// Line by line changes that turn from into to, going by their longest common subsequence
+pub fn diff_lines(from: &str, to: &str) -> Vec<DiffLine> {
+    let from: Vec<&str> = from.lines().collect();
+    let to: Vec<&str> = to.lines().collect();
+
+    // most edits touch a small part of the text, only that part needs the table
+    let prefix = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let suffix = from[prefix..]
+        .iter()
+        .rev()
+        .zip(to[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let from_middle = &from[prefix..from.len() - suffix];
+    let to_middle = &to[prefix..to.len() - suffix];
+
+    let mut lines = Vec::with_capacity(from.len() + to.len());
+    lines.extend(from[..prefix].iter().map(|text| line(DiffOp::Equal, text)));
+    diff_middle(from_middle, to_middle, &mut lines);
+    lines.extend(from[from.len() - suffix..].iter().map(|text| line(DiffOp::Equal, text)));
+    lines
+}
+
+fn diff_middle(from: &[&str], to: &[&str], lines: &mut Vec<DiffLine>) {
+    if (from.len() + 1).saturating_mul(to.len() + 1) > MAX_TABLE_SIZE {
+        lines.extend(from.iter().map(|text| line(DiffOp::Delete, text)));
+        lines.extend(to.iter().map(|text| line(DiffOp::Insert, text)));
+        return;
+    }
+
+    // common[i][j] is the length of the longest common subsequence of from[i..] and to[j..]
+    let width = to.len() + 1;
+    let mut common = vec![0u32; (from.len() + 1) * width];
+    for i in (0..from.len()).rev() {
+        for j in (0..to.len()).rev() {
+            common[i * width + j] = if from[i] == to[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < from.len() && j < to.len() {
+        if from[i] == to[j] {
+            lines.push(line(DiffOp::Equal, from[i]));
+            i += 1;
+            j += 1;
+        } else if common[(i + 1) * width + j] >= common[i * width + j + 1] {
+            lines.push(line(DiffOp::Delete, from[i]));
+            i += 1;
+        } else {
+            lines.push(line(DiffOp::Insert, to[j]));
+            j += 1;
+        }
+    }
+    lines.extend(from[i..].iter().map(|text| line(DiffOp::Delete, text)));
+    lines.extend(to[j..].iter().map(|text| line(DiffOp::Insert, text)));
+}
+
+fn line(op: DiffOp, text: &str) -> DiffLine {
+    DiffLine {
+        op,
+        text: text.to_owned(),
+    }
+}
@@ -0,0 +1,30 @@
+use sha2::{Digest, Sha256};
+
+// Entity tags of articles: the version of the article, then a hash of the response body. Besides
+// the article itself the body shows who favorited it and whether the reader follows its author,
+// and maybe its HTML, so a cached copy only matches while all of that is still the same.
+pub fn article_etag(version: i32, body: &[u8]) -> String {
+    let hash = hex::encode(&Sha256::digest(body)[..12]);
+    format!("\"{}-{}\"", version, hash)
+}
+
+// Whether an If-None-Match header value names the etag. Weak tags are compared by their value.
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+// Whether an If-Match header value names a tag of this version of an article. Updates are only
+// refused for changes to the article, not for a different reader or favorites count.
+pub fn version_matches(header: &str, version: i32) -> bool {
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || etag_version(candidate.trim_start_matches("W/")) == Some(version)
+    })
+}
+
+fn etag_version(etag: &str) -> Option<i32> {
+    let etag = etag.trim_matches('"');
+    etag.split('-').next()?.parse().ok()
+}
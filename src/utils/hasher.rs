@@ -1,15 +1,170 @@
-use libreauth::pass::{Algorithm, HashBuilder, Hasher};
+use hmac::{Hmac, Mac};
+use libreauth::pass::{Algorithm, ErrorCode, HashBuilder, Hasher};
+use sha2::Sha256;
+use std::{collections::HashMap, env};
 
 pub const PWD_ALGORITHM: Algorithm = Algorithm::Argon2;
-pub const PWD_SCHEME_VERSION: usize = 1;
 
-// If the Hasher changes, make sure to increment PWD_SCHEME_VERSION
+// The `ver` libreauth stores with every hash says how the password was fed to Argon2: as typed in,
+// or as its HMAC-SHA256 keyed with PASSWORD_PEPPER
+const PLAIN_SCHEME_VERSION: usize = 1;
+const PEPPERED_SCHEME_VERSION: usize = 2;
+
+// libreauth's own defaults, every hash from before these were configurable was made with them
+const DEFAULT_MEMORY_KIB: u32 = 4096;
+const DEFAULT_ITERATIONS: u32 = 3;
+const DEFAULT_PARALLELISM: u32 = 4;
+
+// Argon2 as configured through PASSWORD_ARGON2_MEMORY_KIB, PASSWORD_ARGON2_ITERATIONS,
+// PASSWORD_ARGON2_PARALLELISM and PASSWORD_PEPPER. Hashes made with other settings keep working
+// and are replaced the next time their user logs in.
+pub struct PasswordHasher {
+    hasher: Hasher,
+    // as libreauth writes them into the stored hash
+    params: HashMap<&'static str, String>,
+    pepper: Option<String>,
+    version: usize,
+}
+
 lazy_static! {
-    pub static ref HASHER: Hasher = {
-        HashBuilder::new()
-            .algorithm(PWD_ALGORITHM)
-            .version(PWD_SCHEME_VERSION)
-            .finalize()
-            .unwrap()
-    };
+    pub static ref HASHER: PasswordHasher = PasswordHasher::from_env()
+        .unwrap_or_else(|e| panic!("Invalid password hashing configuration: {}", e));
+
+    // checked against when there is no user to compare with, so a login for an unknown email
+    // takes as long as one with a wrong password
+    pub static ref DUMMY_PASSWORD_HASH: String = HASHER.hash("not a real password").unwrap();
+}
+
+// Panics if a parameter is out of range, so call this before the server starts accepting requests
+pub fn check_configuration() {
+    lazy_static::initialize(&HASHER);
+}
+
+impl PasswordHasher {
+    fn from_env() -> Result<Self, String> {
+        let memory_kib = env_number("PASSWORD_ARGON2_MEMORY_KIB", DEFAULT_MEMORY_KIB)?;
+        let iterations = env_number("PASSWORD_ARGON2_ITERATIONS", DEFAULT_ITERATIONS)?;
+        let parallelism = env_number("PASSWORD_ARGON2_PARALLELISM", DEFAULT_PARALLELISM)?;
+        let pepper = env::var("PASSWORD_PEPPER").ok().filter(|pepper| !pepper.is_empty());
+
+        // libreauth takes the memory as a power of two and panics on values out of its range
+        if !memory_kib.is_power_of_two() || !(128..=262_144).contains(&memory_kib) {
+            return Err("PASSWORD_ARGON2_MEMORY_KIB must be a power of two from 128 to 262144".into());
+        }
+        if !(1..=1024).contains(&iterations) {
+            return Err("PASSWORD_ARGON2_ITERATIONS must be from 1 to 1024".into());
+        }
+        if !(1..=128).contains(&parallelism) {
+            return Err("PASSWORD_ARGON2_PARALLELISM must be from 1 to 128".into());
+        }
+
+        let mut params = HashMap::new();
+        params.insert("mem", memory_kib.trailing_zeros().to_string());
+        params.insert("passes", iterations.to_string());
+        params.insert("lanes", parallelism.to_string());
+
+        let version = match pepper {
+            Some(_) => PEPPERED_SCHEME_VERSION,
+            None => PLAIN_SCHEME_VERSION,
+        };
+
+        let mut builder = HashBuilder::new();
+        builder.algorithm(PWD_ALGORITHM).version(version);
+        for (key, value) in &params {
+            builder.add_param(key, value);
+        }
+        let hasher = builder.finalize().map_err(|e| format!("{:?}", e))?;
+
+        Ok(PasswordHasher {
+            hasher,
+            params,
+            pepper,
+            version,
+        })
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, ErrorCode> {
+        self.hasher.hash(&self.hash_input(password, self.version)?)
+    }
+
+    pub fn verify(&self, stored_hash: &str, password: &str) -> Result<bool, ErrorCode> {
+        let checker = HashBuilder::from_phc(stored_hash)?;
+        let input = self.hash_input(password, stored_version(stored_hash))?;
+        Ok(checker.is_valid(&input))
+    }
+
+    // Whether the hash was made with other parameters than the current ones, or without the pepper
+    pub fn needs_rehash(&self, stored_hash: &str) -> bool {
+        let (algorithm, stored_params) = parse_phc(stored_hash);
+
+        algorithm != "argon2"
+            || stored_version(stored_hash) != self.version
+            || self
+                .params
+                .iter()
+                .any(|(key, value)| stored_params.get(key) != Some(&value.as_str()))
+    }
+
+    // The settings a hash was made with, readable enough for a report
+    pub fn describe(&self, stored_hash: &str) -> String {
+        let (algorithm, stored_params) = parse_phc(stored_hash);
+        let param = |key| stored_params.get(key).cloned().unwrap_or("?");
+        let memory = match param("mem").parse::<u32>() {
+            Ok(exponent) if exponent < 32 => format!("{}KiB", 1u32 << exponent),
+            _ => "?".into(),
+        };
+        let peppered = stored_version(stored_hash) == PEPPERED_SCHEME_VERSION;
+
+        format!(
+            "{} memory={} iterations={} parallelism={} peppered={}",
+            algorithm,
+            memory,
+            param("passes"),
+            param("lanes"),
+            peppered
+        )
+    }
+
+    fn hash_input(&self, password: &str, version: usize) -> Result<String, ErrorCode> {
+        if version != PEPPERED_SCHEME_VERSION {
+            return Ok(password.to_owned());
+        }
+        // without the pepper these hashes can't be checked at all
+        let pepper = self.pepper.as_ref().ok_or(ErrorCode::InvalidPasswordFormat)?;
+        let mut mac = Hmac::<Sha256>::new_varkey(pepper.as_bytes())
+            .map_err(|_| ErrorCode::InvalidPasswordFormat)?;
+        mac.input(password.as_bytes());
+        Ok(base64::encode(&mac.result().code()))
+    }
+}
+
+fn env_number(name: &str, default: u32) -> Result<u32, String> {
+    match env::var(name) {
+        Ok(value) => value.parse().map_err(|_| format!("{} must be a number, not {}", name, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+// e.g. $argon2$ver=1,lanes=4,passes=3,mem=12,...$salt$hash
+fn parse_phc(stored_hash: &str) -> (&str, HashMap<&str, &str>) {
+    let mut parts = stored_hash.split('$').skip(1);
+    let algorithm = parts.next().unwrap_or_default();
+    let params = parts
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|param| {
+            let mut key_value = param.splitn(2, '=');
+            Some((key_value.next()?, key_value.next()?))
+        })
+        .collect();
+    (algorithm, params)
+}
+
+fn stored_version(stored_hash: &str) -> usize {
+    let (_, params) = parse_phc(stored_hash);
+    params
+        .get("ver")
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(PLAIN_SCHEME_VERSION)
 }
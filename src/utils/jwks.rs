@@ -0,0 +1,162 @@
+// Just enough DER to pull the public key material out of a SubjectPublicKeyInfo PEM,
+// so the keys we sign with can be published as a JSON Web Key Set
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+
+// 1.2.840.113549.1.1.1 and 1.3.101.112
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+const ED25519_KEY_LEN: usize = 32;
+
+pub enum PublicKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    Ed25519 { x: Vec<u8> },
+}
+
+impl PublicKey {
+    pub fn from_pem(pem: &str) -> Result<Self, String> {
+        let der = pem_to_der(pem).ok_or("not base64 between the PEM lines")?;
+
+        let (spki, _) = read_tlv(&der, TAG_SEQUENCE)?;
+        let (algorithm, rest) = read_tlv(spki, TAG_SEQUENCE)?;
+        let (oid, _) = read_tlv(algorithm, TAG_OID)?;
+        let (bit_string, _) = read_tlv(rest, TAG_BIT_STRING)?;
+        // the first byte of a bit string counts the unused bits, always 0 for keys
+        let key = bit_string.get(1..).ok_or("empty key bit string")?;
+
+        if oid == OID_RSA_ENCRYPTION {
+            let (rsa_key, _) = read_tlv(key, TAG_SEQUENCE)?;
+            let (n, rest) = read_tlv(rsa_key, TAG_INTEGER)?;
+            let (e, _) = read_tlv(rest, TAG_INTEGER)?;
+            Ok(PublicKey::Rsa {
+                n: strip_leading_zeros(n).to_vec(),
+                e: strip_leading_zeros(e).to_vec(),
+            })
+        } else if oid == OID_ED25519 && key.len() == ED25519_KEY_LEN {
+            Ok(PublicKey::Ed25519 { x: key.to_vec() })
+        } else {
+            Err("not an RSA or Ed25519 public key".to_owned())
+        }
+    }
+
+    pub fn to_jwk(&self, kid: &str, alg: &str) -> serde_json::Value {
+        match self {
+            PublicKey::Rsa { n, e } => json!({
+                "kty": "RSA",
+                "use": "sig",
+                "alg": alg,
+                "kid": kid,
+                "n": base64_url(n),
+                "e": base64_url(e),
+            }),
+            PublicKey::Ed25519 { x } => json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "use": "sig",
+                "alg": alg,
+                "kid": kid,
+                "x": base64_url(x),
+            }),
+        }
+    }
+}
+
+fn pem_to_der(pem: &str) -> Option<Vec<u8>> {
+    let body = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect::<String>();
+    base64::decode(body.trim()).ok()
+}
+
+// reads one tag-length-value from the front of `der`, returning the value and whatever follows it.
+// The lengths come from the key file, so they're checked against what's actually there.
+fn read_tlv(der: &[u8], tag: u8) -> Result<(&[u8], &[u8]), &'static str> {
+    match der.first() {
+        Some(first) if *first == tag => (),
+        Some(_) => return Err("unexpected DER tag"),
+        None => return Err("DER ends early"),
+    }
+
+    let first_len_byte = *der.get(1).ok_or("DER ends early")?;
+    let (len, header_len) = if first_len_byte & 0x80 == 0 {
+        (first_len_byte as usize, 2)
+    } else {
+        let len_bytes = (first_len_byte & 0x7f) as usize;
+        if len_bytes == 0 || len_bytes > std::mem::size_of::<usize>() {
+            return Err("unsupported DER length");
+        }
+        let len = der
+            .get(2..2 + len_bytes)
+            .ok_or("DER ends early")?
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, 2 + len_bytes)
+    };
+
+    let end = header_len.checked_add(len).ok_or("DER length out of range")?;
+    if end > der.len() {
+        return Err("DER length runs past the end of the key");
+    }
+    Ok((&der[header_len..end], &der[end..]))
+}
+
+fn strip_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let first_non_zero = bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len());
+    &bytes[first_non_zero..]
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_an_ed25519_key() {
+        let pem = "-----BEGIN PUBLIC KEY-----
+MCowBQYDK2VwAyEAreLUp3GaePumDuDGGXpi+sWgXYjc5dqFmvCcGEhEaQE=
+-----END PUBLIC KEY-----
+";
+        let jwk = PublicKey::from_pem(pem).unwrap().to_jwk("key-1", "EdDSA");
+        assert_eq!(jwk["x"], "reLUp3GaePumDuDGGXpi-sWgXYjc5dqFmvCcGEhEaQE");
+    }
+
+    #[test]
+    fn reads_a_value_and_the_rest() {
+        let der = [TAG_INTEGER, 2, 1, 0, TAG_INTEGER, 1, 3];
+        assert_eq!(read_tlv(&der, TAG_INTEGER), Ok((&[1, 0][..], &[TAG_INTEGER, 1, 3][..])));
+    }
+
+    #[test]
+    fn rejects_lengths_past_the_end() {
+        assert!(read_tlv(&[TAG_SEQUENCE, 5, 0], TAG_SEQUENCE).is_err());
+        assert!(read_tlv(&[TAG_SEQUENCE, 0x82, 0x01], TAG_SEQUENCE).is_err());
+    }
+
+    #[test]
+    fn rejects_lengths_that_would_overflow() {
+        let mut der = vec![TAG_SEQUENCE, 0x88];
+        der.extend_from_slice(&[0xff; 8]);
+        assert!(read_tlv(&der, TAG_SEQUENCE).is_err());
+
+        let mut der = vec![TAG_SEQUENCE, 0xff];
+        der.extend_from_slice(&[0xff; 127]);
+        assert!(read_tlv(&der, TAG_SEQUENCE).is_err());
+    }
+
+    #[test]
+    fn rejects_a_hostile_key_file() {
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            base64::encode(&[TAG_SEQUENCE, 0x88, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xf0])
+        );
+        assert!(PublicKey::from_pem(&pem).is_err());
+    }
+}
@@ -1,14 +1,52 @@
 use chrono::{Duration, Utc};
-use jwt::{decode, encode, Header, TokenData, Validation};
-use std::env;
+use jwt::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData,
+    Validation,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value as JsonValue;
+use std::{collections::HashMap, env, fs, path::Path, str::FromStr};
 use uuid::Uuid;
 
-use crate::models::User;
+use super::jwks::PublicKey;
+use crate::models::Session;
 use crate::prelude::*;
 
+// access tokens are short-lived, clients are expected to use their refresh token to get a new one
+pub const ACCESS_TOKEN_LIFETIME_MINUTES: i64 = 15;
+
+// how long a user has to enter their second factor after their password was accepted
+pub const CHALLENGE_LIFETIME_MINUTES: i64 = 5;
+const TOTP_CHALLENGE: &str = "totp";
+
+// only ever accepted when CONDUIT_DEV_MODE is set
+const DEV_SECRET: &str = "secret";
+const DEFAULT_ISSUER: &str = "conduit";
+const DEFAULT_AUDIENCE: &str = "conduit";
+// tolerated clock skew between us and whoever issued a token
+const LEEWAY_SECONDS: i64 = 60;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub id: Uuid,
+    // the session this token was issued for, checked on every request so it can be revoked
+    pub sid: Uuid,
+    pub iss: String,
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+// Handed out in place of an access token when a password was right but a second factor is still needed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChallengeClaims {
+    pub sub: Uuid,
+    // the login_challenges row, which makes the token single use
+    pub jti: Uuid,
+    pub purpose: String,
+    pub iss: String,
+    pub aud: String,
+    pub iat: i64,
     pub exp: i64,
 }
 
@@ -16,16 +54,19 @@ pub trait CanGenerateJwt {
     fn generate_jwt(&self) -> Result<String>;
 }
 
-impl CanGenerateJwt for User {
+impl CanGenerateJwt for Session {
     fn generate_jwt(&self) -> Result<String> {
-        let exp = (Utc::now() + Duration::days(21)).timestamp();
-        let claims = Claims { id: self.id, exp };
+        let now = Utc::now();
+        let claims = Claims {
+            id: self.user_id,
+            sid: self.id,
+            iss: KEYS.issuer.to_owned(),
+            aud: KEYS.audience.to_owned(),
+            iat: now.timestamp(),
+            exp: (now + Duration::minutes(ACCESS_TOKEN_LIFETIME_MINUTES)).timestamp(),
+        };
 
-        let header = Header::default();
-        let secret = &get_secret();
-        let token = encode(&header, &claims, secret.as_ref())?;
-
-        Ok(token)
+        encode_claims(&claims)
     }
 }
 
@@ -35,13 +76,203 @@ pub trait CanDecodeJwt {
 
 impl CanDecodeJwt for String {
     fn decode_jwt(&self) -> Result<TokenData<Claims>> {
-        match decode::<Claims>(&self, get_secret().as_ref(), &Validation::default()) {
-            Ok(res) => Ok(res),
-            Err(e) => Err(e.into()),
-        }
+        let token_data = decode_claims::<Claims>(&self)?;
+        check_issued_at(token_data.claims.iat)?;
+        Ok(token_data)
+    }
+}
+
+pub fn generate_totp_challenge(user_id: Uuid, challenge_id: Uuid) -> Result<String> {
+    let now = Utc::now();
+    let claims = ChallengeClaims {
+        sub: user_id,
+        jti: challenge_id,
+        purpose: TOTP_CHALLENGE.into(),
+        iss: KEYS.issuer.to_owned(),
+        aud: KEYS.audience.to_owned(),
+        iat: now.timestamp(),
+        exp: (now + Duration::minutes(CHALLENGE_LIFETIME_MINUTES)).timestamp(),
+    };
+
+    encode_claims(&claims)
+}
+
+// Returns the ids of the user the challenge was issued for and of the challenge itself
+pub fn decode_totp_challenge(token: &str) -> Result<(Uuid, Uuid)> {
+    let claims = decode_claims::<ChallengeClaims>(token)?.claims;
+    check_issued_at(claims.iat)?;
+
+    if claims.purpose != TOTP_CHALLENGE {
+        return Err(Error::Unauthorized(json!({
+            "error": "Token is invalid",
+        })));
+    }
+
+    Ok((claims.sub, claims.jti))
+}
+
+// Panics if the signing keys are misconfigured, so call this before the server starts accepting requests
+pub fn check_configuration() {
+    lazy_static::initialize(&KEYS);
+}
+
+// The public half of every key we accept, for other services to verify our tokens offline
+pub fn jwks() -> JsonValue {
+    json!({ "keys": KEYS.jwks })
+}
+
+pub fn encode_claims<T: Serialize>(claims: &T) -> Result<String> {
+    let mut header = Header::new(KEYS.algorithm);
+    header.kid = KEYS.signing_kid.clone();
+
+    let token = encode(&header, claims, &KEYS.encoding_key)?;
+
+    Ok(token)
+}
+
+pub fn decode_claims<T: DeserializeOwned>(token: &str) -> Result<TokenData<T>> {
+    let header = decode_header(token)?;
+
+    let decoding_key = KEYS.decoding_keys.get(&header.kid).ok_or_else(|| {
+        Error::Unauthorized(json!({
+            "error": "Token was signed with an unknown key",
+        }))
+    })?;
+
+    let mut validation = Validation::new(KEYS.algorithm);
+    validation.leeway = LEEWAY_SECONDS as u64;
+    validation.set_issuer(&[&KEYS.issuer]);
+    validation.set_audience(&[&KEYS.audience]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+
+    let token_data = decode::<T>(token, decoding_key, &validation)?;
+
+    Ok(token_data)
+}
+
+// jsonwebtoken doesn't check iat, but a token issued in the future was not issued by us
+pub fn check_issued_at(iat: i64) -> Result<()> {
+    if iat > Utc::now().timestamp() + LEEWAY_SECONDS {
+        return Err(Error::Unauthorized(json!({
+            "error": "Token was issued in the future",
+        })));
     }
+    Ok(())
+}
+
+struct JwtKeys {
+    algorithm: Algorithm,
+    signing_kid: Option<String>,
+    encoding_key: EncodingKey,
+    // keyed by the `kid` header, tokens signed with a shared secret don't have one
+    decoding_keys: HashMap<Option<String>, DecodingKey>,
+    jwks: Vec<JsonValue>,
+    issuer: String,
+    audience: String,
+}
+
+lazy_static! {
+    static ref KEYS: JwtKeys = JwtKeys::from_env().unwrap_or_else(|e| panic!("Invalid JWT configuration: {}", e));
 }
 
-fn get_secret() -> String {
-    env::var("JWT_SECRET").unwrap_or_else(|_| "secret".into())
+impl JwtKeys {
+    // With JWT_KEYS_DIR set, tokens are signed with the private key `<JWT_SIGNING_KID>.pem` from that directory,
+    // and every `<kid>.pub.pem` in it is accepted and published, which allows keys to be rotated.
+    // Otherwise tokens are signed with the JWT_SECRET shared secret.
+    fn from_env() -> std::result::Result<Self, String> {
+        let issuer = env::var("JWT_ISSUER").unwrap_or_else(|_| DEFAULT_ISSUER.into());
+        let audience = env::var("JWT_AUDIENCE").unwrap_or_else(|_| DEFAULT_AUDIENCE.into());
+
+        let keys_dir = match env::var("JWT_KEYS_DIR") {
+            Ok(keys_dir) => keys_dir,
+            Err(_) => {
+                let dev_mode = env::var("CONDUIT_DEV_MODE").is_ok();
+                let secret = match env::var("JWT_SECRET") {
+                    Ok(ref secret) if secret != DEV_SECRET => secret.to_owned(),
+                    _ if dev_mode => DEV_SECRET.to_owned(),
+                    _ => {
+                        return Err("set JWT_KEYS_DIR or a JWT_SECRET other than the default, \
+                                    or set CONDUIT_DEV_MODE to run with the default secret"
+                            .into())
+                    }
+                };
+
+                let mut decoding_keys = HashMap::new();
+                decoding_keys.insert(None, DecodingKey::from_secret(secret.as_bytes()));
+
+                return Ok(JwtKeys {
+                    algorithm: Algorithm::HS256,
+                    signing_kid: None,
+                    encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+                    decoding_keys,
+                    // a shared secret must never be published
+                    jwks: vec![],
+                    issuer,
+                    audience,
+                });
+            }
+        };
+
+        let algorithm_name = env::var("JWT_ALGORITHM").unwrap_or_else(|_| "RS256".into());
+        let algorithm = match Algorithm::from_str(&algorithm_name) {
+            Ok(algorithm @ Algorithm::RS256) | Ok(algorithm @ Algorithm::EdDSA) => algorithm,
+            _ => return Err(format!("unsupported JWT_ALGORITHM {}, use RS256 or EdDSA", algorithm_name)),
+        };
+
+        let signing_kid = env::var("JWT_SIGNING_KID")
+            .map_err(|_| "JWT_SIGNING_KID must be set along with JWT_KEYS_DIR".to_string())?;
+
+        let keys_dir = Path::new(&keys_dir);
+        let read_file = |file_name: &str| {
+            fs::read(keys_dir.join(file_name)).map_err(|e| format!("could not read {}: {}", file_name, e))
+        };
+
+        let private_key = read_file(&format!("{}.pem", signing_kid))?;
+        let encoding_key = match algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&private_key),
+            _ => EncodingKey::from_rsa_pem(&private_key),
+        }
+        .map_err(|e| format!("invalid private key {}: {}", signing_kid, e))?;
+
+        let mut decoding_keys = HashMap::new();
+        let mut jwks = vec![];
+
+        let entries = fs::read_dir(keys_dir).map_err(|e| format!("could not read JWT_KEYS_DIR: {}", e))?;
+        for entry in entries {
+            let file_name = entry.map_err(|e| e.to_string())?.file_name();
+            let file_name = file_name.to_string_lossy();
+            let kid = match file_name.rfind(".pub.pem") {
+                Some(end) => &file_name[..end],
+                None => continue,
+            };
+
+            let public_key = read_file(&file_name)?;
+            let decoding_key = match algorithm {
+                Algorithm::EdDSA => DecodingKey::from_ed_pem(&public_key),
+                _ => DecodingKey::from_rsa_pem(&public_key),
+            }
+            .map_err(|e| format!("invalid public key {}: {}", kid, e))?;
+
+            let jwk = PublicKey::from_pem(&String::from_utf8_lossy(&public_key))
+                .map_err(|e| format!("invalid public key {}: {}", kid, e))?
+                .to_jwk(kid, &algorithm_name);
+
+            decoding_keys.insert(Some(kid.to_owned()), decoding_key);
+            jwks.push(jwk);
+        }
+
+        if !decoding_keys.contains_key(&Some(signing_kid.to_owned())) {
+            return Err(format!("{}.pub.pem is missing from JWT_KEYS_DIR", signing_kid));
+        }
+
+        Ok(JwtKeys {
+            algorithm,
+            signing_kid: Some(signing_kid),
+            encoding_key,
+            decoding_keys,
+            jwks,
+            issuer,
+            audience,
+        })
+    }
 }
@@ -0,0 +1,69 @@
+use chrono::Utc;
+use std::{env, fs, path::PathBuf};
+use uuid::Uuid;
+
+use crate::prelude::*;
+
+#[derive(Debug)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Mail {
+    fn to_message(&self) -> String {
+        format!(
+            "To: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+            self.to, self.subject, self.body
+        )
+    }
+}
+
+// Anything that can deliver mail to users, picked with MAIL_TRANSPORT
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<()>;
+}
+
+// Prints every mail, for local development
+pub struct StdoutMailer;
+
+impl Mailer for StdoutMailer {
+    fn send(&self, mail: &Mail) -> Result<()> {
+        println!("{}", mail.to_message());
+        Ok(())
+    }
+}
+
+// Drops every mail as a file into a directory, for local development and tests
+pub struct FileMailer {
+    pub dir: PathBuf,
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: &Mail) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let file_name = format!("{}-{}.eml", Utc::now().format("%Y%m%d%H%M%S%.3f"), Uuid::new_v4());
+        fs::write(self.dir.join(file_name), mail.to_message())?;
+
+        Ok(())
+    }
+}
+
+lazy_static! {
+    pub static ref MAILER: Box<dyn Mailer> = {
+        match env::var("MAIL_TRANSPORT").as_ref().map(String::as_str) {
+            Ok("file") => Box::new(FileMailer {
+                dir: env::var("MAIL_DIR").unwrap_or_else(|_| "mail".into()).into(),
+            }),
+            Ok("stdout") | Err(_) => Box::new(StdoutMailer),
+            Ok(transport) => panic!("Unknown MAIL_TRANSPORT {}", transport),
+        }
+    };
+}
+
+// Called at startup, so an unknown MAIL_TRANSPORT stops the server before the first mail is sent
+pub fn check_configuration() {
+    lazy_static::initialize(&MAILER);
+}
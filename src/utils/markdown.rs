@@ -0,0 +1,23 @@
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+
+lazy_static! {
+    // ammonia's allow-list of tags, attributes and URL schemes; links are marked so the
+    // site doesn't vouch for them
+    static ref SANITIZER: Builder<'static> = {
+        let mut sanitizer = Builder::default();
+        sanitizer.link_rel(Some("noopener noreferrer nofollow"));
+        sanitizer
+    };
+}
+
+// CommonMark, plus tables and strikethrough, rendered to HTML that is safe to put on a page as is.
+// Raw HTML in the Markdown is kept only where the allow-list has it.
+pub fn render_markdown(markdown: &str) -> String {
+    let parser = Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH);
+
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, parser);
+
+    SANITIZER.clean(&unsafe_html).to_string()
+}
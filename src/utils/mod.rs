@@ -1,7 +1,20 @@
 pub mod auth;
 pub mod custom_type;
+pub mod diff;
+pub mod etag;
 pub mod hasher;
+pub mod jwks;
 pub mod jwt;
+pub mod markdown;
+pub mod pagination;
+pub mod mailer;
+pub mod oidc;
+pub mod password_policy;
+pub mod permissions;
+pub mod proxies;
+pub mod token;
+pub mod totp;
+pub mod url;
 
 // just to make it less of a pain to write
 pub use {self::custom_type::*, self::hasher::*};
@@ -0,0 +1,319 @@
+use actix_web::{client::Client, http::header::ACCEPT};
+use futures::{future::result, Future};
+use jwt::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, env, fmt::Display};
+
+use super::url::query_string;
+use crate::prelude::*;
+
+// discovery documents and key sets are small, anything bigger is not what we asked for
+const RESPONSE_LIMIT: usize = 256 * 1024;
+// how long a user has to sign in at the provider
+pub const LOGIN_LIFETIME_MINUTES: i64 = 10;
+
+// An OpenID Connect identity provider users can sign in with, configured through
+// OIDC_<NAME>_ISSUER, OIDC_<NAME>_CLIENT_ID, OIDC_<NAME>_CLIENT_SECRET and OIDC_<NAME>_REDIRECT_URI
+#[derive(Debug)]
+pub struct OidcProvider {
+    pub name: String,
+    issuer: String,
+    client_id: String,
+    // public clients rely on PKCE alone
+    client_secret: Option<String>,
+    redirect_uri: String,
+    scopes: String,
+}
+
+// What we learn about the user from the provider's ID token
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenRequest<'a> {
+    grant_type: &'a str,
+    code: &'a str,
+    redirect_uri: &'a str,
+    client_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<&'a str>,
+    code_verifier: &'a str,
+}
+
+lazy_static! {
+    static ref PROVIDERS: HashMap<String, OidcProvider> =
+        providers_from_env().unwrap_or_else(|e| panic!("Invalid OIDC configuration: {}", e));
+}
+
+// Panics if a provider is misconfigured, so call this before the server starts accepting requests
+pub fn check_configuration() {
+    lazy_static::initialize(&PROVIDERS);
+}
+
+pub fn provider(name: &str) -> Result<&'static OidcProvider> {
+    PROVIDERS.get(name).ok_or_else(|| {
+        Error::NotFound(json!({
+            "error": format!("there is no identity provider called {}", name),
+        }))
+    })
+}
+
+impl OidcProvider {
+    // Where to send the user to sign in. The provider sends them back to our callback with a code
+    // that only the holder of code_verifier can exchange.
+    pub fn authorization_url(
+        &'static self,
+        state: String,
+        nonce: String,
+        code_verifier: &str,
+    ) -> impl Future<Item = String, Error = Error> {
+        let code_challenge = pkce_challenge(code_verifier);
+
+        self.discover().map(move |discovery| {
+            let query = query_string(&[
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", &self.scopes),
+                ("state", &state),
+                ("nonce", &nonce),
+                ("code_challenge", &code_challenge),
+                ("code_challenge_method", "S256"),
+            ]);
+            let separator = if discovery.authorization_endpoint.contains('?') { '&' } else { '?' };
+            format!("{}{}{}", discovery.authorization_endpoint, separator, query)
+        })
+    }
+
+    // Whether our callback is only reached over HTTPS, so cookies for it can be kept off plain HTTP
+    pub fn secure_callback(&self) -> bool {
+        self.redirect_uri.starts_with("https://")
+    }
+
+    // Trades the code from the callback for an ID token and checks that the token is genuine,
+    // meant for us and belongs to the sign-in that was started with this nonce
+    pub fn sign_in(
+        &'static self,
+        code: String,
+        code_verifier: String,
+        nonce: String,
+    ) -> impl Future<Item = IdTokenClaims, Error = Error> {
+        self.discover().and_then(move |discovery| {
+            let token_request = TokenRequest {
+                grant_type: "authorization_code",
+                code: &code,
+                redirect_uri: &self.redirect_uri,
+                client_id: &self.client_id,
+                client_secret: self.client_secret.as_deref(),
+                code_verifier: &code_verifier,
+            };
+
+            Client::default()
+                .post(&discovery.token_endpoint)
+                .header(ACCEPT, "application/json")
+                .send_form(&token_request)
+                .map_err(move |e| self.error("exchange the code", e))
+                .and_then(move |mut response| {
+                    let status = response.status();
+                    let json = response.json::<TokenResponse>().limit(RESPONSE_LIMIT);
+                    result(if status.is_success() { Ok(()) } else { Err(self.error("exchange the code", status)) })
+                        .and_then(move |_| json.map_err(move |e| self.error("exchange the code", e)))
+                })
+                .and_then(move |token_response| {
+                    self.fetch_json::<JwkSet>(&discovery.jwks_uri, "fetch its keys")
+                        .and_then(move |jwks| {
+                            self.verify_id_token(&token_response.id_token, &discovery, &jwks, &nonce)
+                        })
+                })
+        })
+    }
+
+    fn discover(&'static self) -> impl Future<Item = Discovery, Error = Error> {
+        let url = format!("{}/.well-known/openid-configuration", self.issuer);
+
+        self.fetch_json::<Discovery>(&url, "serve its configuration")
+            .and_then(move |discovery| {
+                // whoever serves the configuration also decides which tokens we trust
+                if discovery.issuer.trim_end_matches('/') != self.issuer {
+                    return Err(self.error("serve its configuration", "the issuer doesn't match"));
+                }
+                Ok(discovery)
+            })
+    }
+
+    fn verify_id_token(
+        &self,
+        id_token: &str,
+        discovery: &Discovery,
+        jwks: &JwkSet,
+        nonce: &str,
+    ) -> Result<IdTokenClaims> {
+        let header = decode_header(id_token)?;
+
+        // a provider with a single key doesn't always bother with key ids
+        let jwk = match header.kid {
+            Some(ref kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| self.error("sign the ID token", "no matching key"))?;
+
+        // the token names its algorithm, but only one the key was made for is taken from it
+        if !key_signs_with(jwk, header.alg) {
+            return Err(self.error(
+                "sign the ID token",
+                format!("{:?} isn't an algorithm its key signs with", header.alg),
+            ));
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_audience(&[&self.client_id]);
+
+        let claims = decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+
+        // ties the token to this sign-in, against tokens replayed from another one
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(self.error("sign the ID token", "the nonce doesn't match"));
+        }
+
+        Ok(claims)
+    }
+
+    fn fetch_json<T>(&'static self, url: &str, what: &'static str) -> impl Future<Item = T, Error = Error>
+    where
+        T: serde::de::DeserializeOwned + 'static,
+    {
+        Client::default()
+            .get(url)
+            .header(ACCEPT, "application/json")
+            .send()
+            .map_err(move |e| self.error(what, e))
+            .and_then(move |mut response| {
+                let status = response.status();
+                let json = response.json::<T>().limit(RESPONSE_LIMIT);
+                result(if status.is_success() { Ok(()) } else { Err(self.error(what, status)) })
+                    .and_then(move |_| json.map_err(move |e| self.error(what, e)))
+            })
+    }
+
+    // The details only go to the log, the user just learns that signing in didn't work
+    fn error<E: Display>(&self, what: &str, error: E) -> Error {
+        log::warn!("identity provider {} failed to {}: {}", self.name, what, error);
+        Error::Unauthorized(json!({
+            "error": format!("Signing in with {} failed", self.name),
+        }))
+    }
+}
+
+// Only asymmetric algorithms that fit the type of the key, and the one the key is published for
+// if the provider says. Anything else would let whoever made the token choose how it's checked.
+fn key_signs_with(jwk: &Jwk, alg: Algorithm) -> bool {
+    use Algorithm::*;
+
+    if matches!(jwk.common.algorithm, Some(published) if published != alg) {
+        return false;
+    }
+
+    match (&jwk.algorithm, alg) {
+        (AlgorithmParameters::RSA(_), alg) => {
+            matches!(alg, RS256 | RS384 | RS512 | PS256 | PS384 | PS512)
+        }
+        (AlgorithmParameters::EllipticCurve(key), ES256) => key.curve == EllipticCurve::P256,
+        (AlgorithmParameters::EllipticCurve(key), ES384) => key.curve == EllipticCurve::P384,
+        (AlgorithmParameters::OctetKeyPair(key), EdDSA) => key.curve == EllipticCurve::Ed25519,
+        _ => false,
+    }
+}
+
+// S256, the only method that keeps an intercepted authorization request from being useful
+fn pkce_challenge(code_verifier: &str) -> String {
+    base64::encode_config(&Sha256::digest(code_verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+// OIDC_PROVIDERS is a comma separated list of names, each configured through its own variables
+fn providers_from_env() -> std::result::Result<HashMap<String, OidcProvider>, String> {
+    let names = env::var("OIDC_PROVIDERS").unwrap_or_default();
+
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let var = |suffix: &str| env::var(format!("OIDC_{}_{}", name.to_uppercase(), suffix));
+            let required = |suffix: &str| {
+                var(suffix).map_err(|_| format!("OIDC_{}_{} must be set", name.to_uppercase(), suffix))
+            };
+
+            let provider = OidcProvider {
+                name: name.to_owned(),
+                issuer: required("ISSUER")?.trim_end_matches('/').to_owned(),
+                client_id: required("CLIENT_ID")?,
+                client_secret: var("CLIENT_SECRET").ok(),
+                redirect_uri: required("REDIRECT_URI")?,
+                scopes: var("SCOPES").unwrap_or_else(|_| "openid email profile".into()),
+            };
+            Ok((name.to_owned(), provider))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwk(key: serde_json::Value) -> Jwk {
+        serde_json::from_value(key).unwrap()
+    }
+
+    #[test]
+    fn only_takes_algorithms_the_key_signs_with() {
+        let rsa = jwk(json!({ "kty": "RSA", "n": "AQAB", "e": "AQAB" }));
+        assert!(key_signs_with(&rsa, Algorithm::RS256));
+        assert!(key_signs_with(&rsa, Algorithm::PS512));
+        assert!(!key_signs_with(&rsa, Algorithm::HS256));
+        assert!(!key_signs_with(&rsa, Algorithm::ES256));
+
+        let p256 = jwk(json!({ "kty": "EC", "crv": "P-256", "x": "AQAB", "y": "AQAB" }));
+        assert!(key_signs_with(&p256, Algorithm::ES256));
+        assert!(!key_signs_with(&p256, Algorithm::ES384));
+
+        let ed25519 = jwk(json!({ "kty": "OKP", "crv": "Ed25519", "x": "AQAB" }));
+        assert!(key_signs_with(&ed25519, Algorithm::EdDSA));
+        assert!(!key_signs_with(&ed25519, Algorithm::HS512));
+
+        // a secret shared with the provider is never one to check its tokens with
+        let secret = jwk(json!({ "kty": "oct", "k": "AQAB" }));
+        assert!(!key_signs_with(&secret, Algorithm::HS256));
+    }
+
+    #[test]
+    fn sticks_to_the_algorithm_a_key_is_published_for() {
+        let rsa = jwk(json!({ "kty": "RSA", "alg": "RS256", "n": "AQAB", "e": "AQAB" }));
+        assert!(key_signs_with(&rsa, Algorithm::RS256));
+        assert!(!key_signs_with(&rsa, Algorithm::PS256));
+    }
+}
@@ -0,0 +1,179 @@
+use actix_web::web::Query;
+use chrono::NaiveDateTime;
+use std::{fmt::Display, str::FromStr};
+
+use crate::prelude::*;
+use crate::utils::url::query_string;
+
+// Points at an item of a list by what the list is sorted on, a time and then the id for items from
+// the same moment. A page that starts after it stays put however many items come or go before it.
+// Clients get it as an opaque string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor<Id> {
+    pub time: NaiveDateTime,
+    pub id: Id,
+}
+
+impl<Id: Display + FromStr> Cursor<Id> {
+    pub fn encode(&self) -> String {
+        let key = format!(
+            "{}.{}.{}",
+            self.time.timestamp(),
+            self.time.timestamp_subsec_nanos(),
+            self.id
+        );
+        base64::encode_config(&key, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Cursor<Id>> {
+        let key = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+        let key = String::from_utf8(key).ok()?;
+        let mut parts = key.splitn(3, '.');
+        let secs = parts.next()?.parse().ok()?;
+        let nanos = parts.next()?.parse().ok()?;
+        Some(Cursor {
+            time: NaiveDateTime::from_timestamp_opt(secs, nanos)?,
+            id: parts.next()?.parse().ok()?,
+        })
+    }
+}
+
+// Which part of a list was asked for: counted from its start, or right after or right before an
+// item in the order of the list
+#[derive(Debug)]
+pub enum Page<Id> {
+    Offset(i64),
+    After(Cursor<Id>),
+    Before(Cursor<Id>),
+}
+
+impl<Id: Display + FromStr> Page<Id> {
+    pub fn parse(
+        offset: Option<usize>,
+        after: &Option<String>,
+        before: &Option<String>,
+    ) -> Result<Page<Id>> {
+        let cursor = |name: &str, cursor: &str| {
+            Cursor::decode(cursor).ok_or_else(|| {
+                Error::UnprocessableEntity(json!({
+                    "errors": { name: ["is not a valid cursor"] },
+                }))
+            })
+        };
+
+        match (offset, after, before) {
+            (offset, None, None) => Ok(Page::Offset(offset.unwrap_or(0) as i64)),
+            (None, Some(after), None) => Ok(Page::After(cursor("after", after)?)),
+            (None, None, Some(before)) => Ok(Page::Before(cursor("before", before)?)),
+            _ => Err(Error::UnprocessableEntity(json!({
+                "errors": { "after": ["can't be combined with before or offset"] },
+            }))),
+        }
+    }
+
+    // Takes up to limit + 1 items loaded for this page, in the order they were read, and keeps the
+    // page along with cursors to the pages around it
+    pub fn finish<T, F>(&self, mut items: Vec<T>, limit: i64, cursor_of: F) -> (Vec<T>, PageLinks)
+    where
+        F: Fn(&T) -> Cursor<Id>,
+    {
+        let has_more = items.len() as i64 > limit;
+        items.truncate(limit.max(0) as usize);
+        // before pages are read walking the list backwards, from the cursor towards its start
+        if let Page::Before(_) = self {
+            items.reverse();
+        }
+
+        let first = items.first().map(|item| cursor_of(item).encode());
+        let last = items.last().map(|item| cursor_of(item).encode());
+        // an empty cursor page has no link back: pointing it at the cursor would skip the cursor's
+        // own item
+        let links = match self {
+            Page::Offset(offset) => PageLinks {
+                next: if has_more { last } else { None },
+                prev: if *offset > 0 { first } else { None },
+            },
+            Page::After(_) => PageLinks {
+                next: if has_more { last } else { None },
+                prev: first,
+            },
+            Page::Before(_) => PageLinks {
+                next: last,
+                prev: if has_more { first } else { None },
+            },
+        };
+
+        (items, links)
+    }
+}
+
+// Cursors to the neighbouring pages of a list, sent in the Link header
+#[derive(Debug, Default)]
+pub struct PageLinks {
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+// Links to the neighbouring pages, as the request for this one with its cursor or offset replaced
+pub fn link_header(path: &str, query: &str, links: &PageLinks) -> Option<String> {
+    let params = Query::<Vec<(String, String)>>::from_query(query)
+        .map(Query::into_inner)
+        .unwrap_or_default();
+    let params: Vec<(&str, &str)> = params
+        .iter()
+        .filter(|(name, _)| !["after", "before", "offset"].contains(&name.as_str()))
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+
+    let link = |param: &str, cursor: &str, rel: &str| {
+        let mut params = params.clone();
+        params.push((param, cursor));
+        format!("<{}?{}>; rel=\"{}\"", path, query_string(&params), rel)
+    };
+
+    let mut header = Vec::new();
+    if let Some(ref cursor) = links.next {
+        header.push(link("after", cursor, "next"));
+    }
+    if let Some(ref cursor) = links.prev {
+        header.push(link("before", cursor, "prev"));
+    }
+
+    match header.is_empty() {
+        true => None,
+        false => Some(header.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(id: i32) -> Cursor<i32> {
+        Cursor {
+            time: NaiveDateTime::from_timestamp(1_600_000_000, 0),
+            id,
+        }
+    }
+
+    #[test]
+    fn links_back_from_a_page_after_a_cursor() {
+        let (items, links) = Page::After(cursor(1)).finish(vec![2, 3], 2, |id| cursor(*id));
+        assert_eq!(items, vec![2, 3]);
+        assert_eq!(links.prev, Some(cursor(2).encode()));
+        assert_eq!(links.next, None);
+    }
+
+    #[test]
+    fn an_empty_last_page_has_no_links() {
+        let (items, links) = Page::After(cursor(3)).finish(Vec::new(), 2, |id| cursor(*id));
+        assert!(items.is_empty());
+        assert_eq!(links.prev, None);
+        assert_eq!(links.next, None);
+
+        let (items, links) = Page::Before(cursor(1)).finish(Vec::new(), 2, |id| cursor(*id));
+        assert!(items.is_empty());
+        assert_eq!(links.prev, None);
+        assert_eq!(links.next, None);
+    }
+}
@@ -0,0 +1,164 @@
+use sha1::{Digest, Sha1};
+use std::{
+    borrow::Cow,
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+};
+use validator::{ValidationError, ValidationErrors};
+
+use crate::prelude::*;
+
+// roughly what 8 random lowercase letters and digits are worth
+const DEFAULT_MIN_ENTROPY_BITS: f64 = 40.0;
+// shorter usernames turn up inside too many unrelated passwords
+const MIN_IDENTIFIER_LENGTH: usize = 3;
+
+// What a new password has to live up to, on top of the 8-72 characters checked by the validators.
+// PASSWORD_MIN_ENTROPY_BITS sets how hard to guess it has to be, PASSWORD_BREACH_DIR points at
+// Have I Been Pwned style range files: one file per 5 character SHA-1 prefix, called e.g.
+// 21BD1.txt, with a SUFFIX:COUNT line for each breached password.
+#[derive(Debug)]
+pub struct PasswordPolicy {
+    min_entropy_bits: f64,
+    breach_dir: Option<PathBuf>,
+}
+
+lazy_static! {
+    pub static ref PASSWORD_POLICY: PasswordPolicy = {
+        let min_entropy_bits = match env::var("PASSWORD_MIN_ENTROPY_BITS") {
+            Ok(bits) => bits.parse().unwrap_or_else(|_| {
+                panic!("PASSWORD_MIN_ENTROPY_BITS must be a number, not {}", bits)
+            }),
+            Err(_) => DEFAULT_MIN_ENTROPY_BITS,
+        };
+        let breach_dir = env::var("PASSWORD_BREACH_DIR").ok().map(PathBuf::from);
+        if let Some(ref dir) = breach_dir {
+            if !dir.is_dir() {
+                panic!("PASSWORD_BREACH_DIR {} is not a directory", dir.display());
+            }
+        }
+        PasswordPolicy {
+            min_entropy_bits,
+            breach_dir,
+        }
+    };
+}
+
+// Panics if the policy is misconfigured, so call this before the server starts accepting requests
+pub fn check_configuration() {
+    lazy_static::initialize(&PASSWORD_POLICY);
+}
+
+impl PasswordPolicy {
+    // The username and email are the ones the account will have once the password is set
+    pub fn check(&self, password: &str, username: &str, email: &str) -> Result<()> {
+        let mut errors = ValidationErrors::new();
+
+        if estimated_entropy_bits(password) < self.min_entropy_bits {
+            errors.add("password", error("too_weak", "fails validation - is too easy to guess"));
+        }
+        if contains_identifier(password, username, email) {
+            errors.add(
+                "password",
+                error(
+                    "contains_identifier",
+                    "fails validation - must not contain the username or email",
+                ),
+            );
+        }
+        if self.is_breached(password)? {
+            errors.add(
+                "password",
+                error(
+                    "breached",
+                    "fails validation - has appeared in a data breach, choose another one",
+                ),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.into())
+        }
+    }
+
+    // Only the range file for the first 5 hex digits of the hash is read, the same file covers
+    // many passwords so the list can be kept in smaller pieces
+    fn is_breached(&self, password: &str) -> Result<bool> {
+        let dir = match self.breach_dir {
+            Some(ref dir) => dir,
+            None => return Ok(false),
+        };
+
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        let file = match File::open(dir.join(format!("{}.txt", prefix))) {
+            Ok(file) => file,
+            // the list doesn't have to be complete
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let breached_suffix = line.split(':').next().unwrap_or_default().trim();
+            if breached_suffix.eq_ignore_ascii_case(suffix) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
+
+// A rough upper bound of the guesses needed, going by which kinds of characters are used. Repeated
+// characters and runs like "aaaa" or "1234" add next to nothing to that.
+fn estimated_entropy_bits(password: &str) -> f64 {
+    let mut pool_size = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool_size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool_size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool_size += 10;
+    }
+    if password.chars().any(|c| !c.is_ascii_alphanumeric()) {
+        pool_size += 33;
+    }
+
+    let mut counted = 0;
+    let mut previous: Option<char> = None;
+    for c in password.chars() {
+        let continues_run = match previous {
+            Some(p) => (c as i64 - p as i64).abs() <= 1,
+            None => false,
+        };
+        if !continues_run {
+            counted += 1;
+        }
+        previous = Some(c);
+    }
+
+    counted as f64 * f64::from(pool_size).log2()
+}
+
+fn contains_identifier(password: &str, username: &str, email: &str) -> bool {
+    let password = password.to_lowercase();
+    let local_part = email.split('@').next().unwrap_or_default();
+
+    [username, local_part]
+        .iter()
+        .filter(|identifier| identifier.chars().count() >= MIN_IDENTIFIER_LENGTH)
+        .any(|identifier| password.contains(&identifier.to_lowercase()))
+}
@@ -0,0 +1,103 @@
+use uuid::Uuid;
+
+use crate::models::User;
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "user" => Some(Role::User),
+            "moderator" => Some(Role::Moderator),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn has_permission(self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Moderator => match permission {
+                Permission::EditArticle | Permission::DeleteArticle | Permission::DeleteComment => true,
+                Permission::ManageUsers => false,
+            },
+            Role::User => false,
+        }
+    }
+}
+
+// What a role may do to content that belongs to someone else, owners can always act on their own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    EditArticle,
+    DeleteArticle,
+    DeleteComment,
+    // everything under /api/admin
+    ManageUsers,
+}
+
+impl Permission {
+    fn as_str(self) -> &'static str {
+        match self {
+            Permission::EditArticle => "edit article",
+            Permission::DeleteArticle => "delete article",
+            Permission::DeleteComment => "delete comment",
+            Permission::ManageUsers => "manage user",
+        }
+    }
+
+    fn forbidden_error(self) -> Error {
+        let message = match self {
+            Permission::EditArticle | Permission::DeleteArticle => {
+                "user is not the author of article in question"
+            }
+            Permission::DeleteComment => "user did not make this comment",
+            Permission::ManageUsers => "user is not an admin",
+        };
+        Error::Forbidden(json!({ "error": message }))
+    }
+}
+
+// Checks that a user may act on something owned by owner_id, either by owning it or through
+// their role. Returns the role that allowed it in the latter case, so it can be reported back.
+pub fn authorize(user: &User, owner_id: Uuid, permission: Permission, target: &str) -> Result<Option<Role>> {
+    if user.id == owner_id {
+        return Ok(None);
+    }
+
+    let role = require_permission(user, permission)?;
+
+    log::info!(
+        "{} acted as {} to {} {} owned by {}",
+        user.username,
+        role.as_str(),
+        permission.as_str(),
+        target,
+        owner_id
+    );
+    Ok(Some(role))
+}
+
+// For actions that aren't about anyone's content, only the role counts
+pub fn require_permission(user: &User, permission: Permission) -> Result<Role> {
+    let role = user.role();
+    if !role.has_permission(permission) {
+        return Err(permission.forbidden_error());
+    }
+    Ok(role)
+}
@@ -0,0 +1,160 @@
+use std::{env, net::IpAddr};
+
+// Where requests may come through a reverse proxy, TRUSTED_PROXIES lists the proxies' addresses,
+// comma separated, each either an IP address or a CIDR range like 10.0.0.0/8. Only requests whose
+// peer is one of them get their client address from X-Forwarded-For, anybody else could put
+// whatever they like in there.
+#[derive(Debug)]
+pub struct TrustedProxies {
+    ranges: Vec<IpRange>,
+}
+
+#[derive(Debug, PartialEq)]
+struct IpRange {
+    addr: IpAddr,
+    prefix_len: u32,
+}
+
+lazy_static! {
+    pub static ref TRUSTED_PROXIES: TrustedProxies = {
+        let ranges = env::var("TRUSTED_PROXIES").unwrap_or_default();
+        TrustedProxies::parse(&ranges).unwrap_or_else(|range| {
+            panic!("TRUSTED_PROXIES has an invalid address or range {}", range)
+        })
+    };
+}
+
+// Panics on an invalid TRUSTED_PROXIES, so call this before the server starts accepting requests
+pub fn check_configuration() {
+    lazy_static::initialize(&TRUSTED_PROXIES);
+}
+
+impl TrustedProxies {
+    // Fails with the first entry that isn't an address or a range
+    fn parse(ranges: &str) -> Result<TrustedProxies, String> {
+        let ranges = ranges
+            .split(',')
+            .map(str::trim)
+            .filter(|range| !range.is_empty())
+            .map(|range| IpRange::parse(range).ok_or_else(|| range.to_owned()))
+            .collect::<Result<_, _>>()?;
+        Ok(TrustedProxies { ranges })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(addr))
+    }
+
+    // The address of whoever made the request. Each proxy appends the address it got the request
+    // from to X-Forwarded-For, so it's read from the end, for as long as the hops are trusted.
+    pub fn client_addr(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        if !self.contains(peer) {
+            return peer;
+        }
+
+        let hops = forwarded_for
+            .unwrap_or_default()
+            .rsplit(',')
+            .map(|hop| hop.trim().parse::<IpAddr>());
+
+        let mut client = peer;
+        for hop in hops {
+            match hop {
+                Ok(addr) => client = addr,
+                // whatever came before a mangled entry can't be told apart from made up ones
+                Err(_) => break,
+            }
+            if !self.contains(client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+impl IpRange {
+    fn parse(range: &str) -> Option<IpRange> {
+        let mut parts = range.splitn(2, '/');
+        let addr = parts.next()?.parse::<IpAddr>().ok()?;
+        let max_prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_len = match parts.next() {
+            Some(prefix_len) => prefix_len.parse().ok().filter(|&len| len <= max_prefix_len)?,
+            None => max_prefix_len,
+        };
+        Some(IpRange { addr, prefix_len })
+    }
+
+    fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(range), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(range) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(range) & mask == u128::from(addr) & mask
+            }
+            // an IPv4 client reaching an IPv6 socket shows up as ::ffff:a.b.c.d
+            (IpAddr::V4(_), IpAddr::V6(addr)) => match (addr.segments(), addr.to_ipv4()) {
+                ([0, 0, 0, 0, 0, 0xffff, ..], Some(addr)) => self.contains(IpAddr::V4(addr)),
+                _ => false,
+            },
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_addresses_and_ranges() {
+        let proxies = TrustedProxies::parse(" 10.0.0.0/8, 192.168.1.7,fd00::/8 ,").unwrap();
+        assert_eq!(proxies.ranges.len(), 3);
+        assert!(proxies.contains(addr("10.200.3.4")));
+        assert!(proxies.contains(addr("192.168.1.7")));
+        assert!(!proxies.contains(addr("192.168.1.8")));
+        assert!(proxies.contains(addr("fd12::1")));
+        assert!(proxies.contains(addr("::ffff:10.1.2.3")));
+        assert!(!proxies.contains(addr("11.0.0.1")));
+
+        assert_eq!(TrustedProxies::parse("10.0.0.0/33").unwrap_err(), "10.0.0.0/33");
+        assert_eq!(TrustedProxies::parse("proxy.local").unwrap_err(), "proxy.local");
+        assert!(TrustedProxies::parse("").unwrap().ranges.is_empty());
+    }
+
+    #[test]
+    fn ignores_forwarded_for_from_untrusted_peers() {
+        let proxies = TrustedProxies::parse("10.0.0.1").unwrap();
+        let client = proxies.client_addr(addr("203.0.113.9"), Some("198.51.100.1"));
+        assert_eq!(client, addr("203.0.113.9"));
+
+        let nobody_trusted = TrustedProxies::parse("").unwrap();
+        let client = nobody_trusted.client_addr(addr("10.0.0.1"), Some("198.51.100.1"));
+        assert_eq!(client, addr("10.0.0.1"));
+    }
+
+    #[test]
+    fn takes_the_last_untrusted_hop() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        // the client made up the first entry, the proxies added the rest
+        let forwarded_for = Some("1.2.3.4, 198.51.100.1, 10.0.0.2");
+        let client = proxies.client_addr(addr("10.0.0.1"), forwarded_for);
+        assert_eq!(client, addr("198.51.100.1"));
+
+        let client = proxies.client_addr(addr("10.0.0.1"), Some("garbage, 198.51.100.1"));
+        assert_eq!(client, addr("198.51.100.1"));
+        let client = proxies.client_addr(addr("10.0.0.1"), Some("198.51.100.1, garbage"));
+        assert_eq!(client, addr("10.0.0.1"));
+        let client = proxies.client_addr(addr("10.0.0.1"), None);
+        assert_eq!(client, addr("10.0.0.1"));
+    }
+}
@@ -0,0 +1,12 @@
+use libreauth::key::KeyBuilder;
+use sha2::{Digest, Sha256};
+
+// Opaque tokens (refresh tokens and the like) are random bytes handed to the client once.
+// Only their sha256 is stored, so a database leak doesn't hand out working tokens.
+pub fn generate_token() -> String {
+    KeyBuilder::new().size(32).generate().as_hex()
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,68 @@
+use chrono::Utc;
+use libreauth::{key::KeyBuilder, oath::TOTPBuilder};
+use std::env;
+
+use super::{token::hash_token, url::percent_encode};
+use crate::prelude::*;
+
+// 160 bits, as recommended by RFC 4226
+const SECRET_SIZE: usize = 20;
+const PERIOD_SECONDS: i64 = 30;
+const TOLERANCE_STEPS: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_SIZE: usize = 8;
+
+pub fn generate_secret() -> String {
+    KeyBuilder::new().size(SECRET_SIZE).generate().as_base32()
+}
+
+// The URI authenticator apps expect, usually shown to the user as a QR code
+pub fn otpauth_uri(secret: &str, account_name: &str) -> String {
+    let issuer = env::var("TOTP_ISSUER").unwrap_or_else(|_| "Conduit".into());
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits=6&period=30",
+        issuer = percent_encode(&issuer),
+        account = percent_encode(account_name),
+        secret = secret,
+    )
+}
+
+// Returns the time step the code was generated for, or None if it isn't a current code. Callers
+// remember the step of the code they accept, so the same code can't be used twice.
+pub fn matching_time_step(secret: &str, code: &str) -> Result<Option<i64>> {
+    let current_step = Utc::now().timestamp() / PERIOD_SECONDS;
+
+    // also accept the previous and next code, for clocks that are slightly off
+    for step in (current_step - TOLERANCE_STEPS)..=(current_step + TOLERANCE_STEPS) {
+        let totp = TOTPBuilder::new()
+            .base32_key(secret)
+            .period(PERIOD_SECONDS as u32)
+            .timestamp(step * PERIOD_SECONDS)
+            .finalize()?;
+        if totp.is_valid(code.trim()) {
+            return Ok(Some(step));
+        }
+    }
+
+    Ok(None)
+}
+
+// Returns the codes to show the user once, only their hashes should be stored
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code = KeyBuilder::new().size(RECOVERY_CODE_SIZE).generate().as_hex();
+            format!("{}-{}", &code[..RECOVERY_CODE_SIZE], &code[RECOVERY_CODE_SIZE..])
+        })
+        .collect()
+}
+
+// recovery codes get typed in by hand, so dashes, spaces and case don't matter
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+    hash_token(&normalized)
+}